@@ -1,22 +1,38 @@
-use std::{collections::HashMap, fs};
+use std::{cell::Cell, collections::HashMap, fs};
 
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
 use graphql_client::{GraphQLQuery, Response};
 use log::{debug, info};
 use orders::OrdersGetUserMarketOfferOrdersList;
-use reqwest::Client;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use tokio::time::Duration;
+use tracing::{field, info_span, Instrument};
 
-use crate::{errors::ForbiddenError, traits::Signer};
+use crate::{
+    errors::{ForbiddenError, MagmaError, RateLimitedError},
+    traits::Signer,
+};
 
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "resources/graphql/schema.graphql",
     query_path = "resources/graphql/Orders.graphql",
-    response_derives = "Debug, Deserialize"
+    response_derives = "Debug, Deserialize, Serialize, Clone"
 )]
 pub struct Orders;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "resources/graphql/schema.graphql",
+    query_path = "resources/graphql/ActionableOrders.graphql",
+    response_derives = "Debug, Deserialize"
+)]
+struct ActionableOrders;
+
 #[derive(GraphQLQuery)]
 #[graphql(
     schema_path = "resources/graphql/schema.graphql",
@@ -79,16 +95,87 @@ struct GetNodeAddress;
 )]
 struct CreateApiKey;
 
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "resources/graphql/schema.graphql",
+    query_path = "resources/graphql/MarketOffers.graphql",
+    response_derives = "Debug, Deserialize, Clone"
+)]
+pub struct MarketOffers;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "resources/graphql/schema.graphql",
+    query_path = "resources/graphql/CreateOffer.graphql",
+    response_derives = "Debug, Deserialize",
+    variables_derives = "Deserialize"
+)]
+struct CreateOffer;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "resources/graphql/schema.graphql",
+    query_path = "resources/graphql/UpdateOffer.graphql",
+    response_derives = "Debug, Deserialize",
+    variables_derives = "Deserialize"
+)]
+struct UpdateOffer;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "resources/graphql/schema.graphql",
+    query_path = "resources/graphql/ToggleOffer.graphql",
+    response_derives = "Debug, Deserialize"
+)]
+struct ToggleOffer;
+
+#[derive(GraphQLQuery)]
+#[graphql(
+    schema_path = "resources/graphql/schema.graphql",
+    query_path = "resources/graphql/SendMessage.graphql",
+    response_derives = "Debug, Deserialize"
+)]
+struct SendMessage;
+
+mod offers;
+mod throttle;
+
+pub use offers::{MarketOffer, OfferChanges, OfferConfig};
+pub use throttle::QueryBudget;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct MagmaConfig {
     pub api_key: Option<String>,
     pub api_key_expiration: Option<f64>,
+    /// Offers to keep in sync with Magma on startup
+    pub offers: Option<Vec<OfferConfig>>,
 }
 
 const API_KEY_FILE: &str = ".amboss_magma_bot.jwt";
 
+/// Response of `ActionableOrders`. Its orders select the same `OrderFields`
+/// as `Orders`, so they're read into the type generated for that query.
+#[derive(Deserialize)]
+struct ActionableOrdersData {
+    #[serde(rename = "getUser")]
+    get_user: ActionableOrdersUser,
+}
+
+#[derive(Deserialize)]
+struct ActionableOrdersUser {
+    pubkeys: Vec<String>,
+    /// Orders keyed by the alias of their status
+    market: Option<HashMap<String, OrderList>>,
+}
+
+#[derive(Deserialize)]
+struct OrderList {
+    list: Vec<OrdersGetUserMarketOfferOrdersList>,
+}
+
 pub struct Api {
     config: MagmaConfig,
+    budget: Cell<Option<QueryBudget>>,
 }
 
 fn log_cost(extensions: Option<HashMap<String, Value>>) {
@@ -109,11 +196,28 @@ fn log_cost(extensions: Option<HashMap<String, Value>>) {
     }
 }
 
+fn magma_error(e: reqwest::Error) -> MagmaError {
+    MagmaError(e.to_string())
+}
+
+fn has_error_code(errors: &[graphql_client::Error], code: &str) -> bool {
+    errors.iter().any(|e| {
+        e.extensions
+            .as_ref()
+            .and_then(|ext| ext.get("code"))
+            .and_then(|value| value.as_str())
+            .is_some_and(|value| value == code)
+    })
+}
+
 impl Api {
     const API_URL: &str = "https://api.amboss.space/graphql";
 
     pub fn new(config: MagmaConfig) -> Self {
-        Api { config }
+        Api {
+            config,
+            budget: Cell::new(None),
+        }
     }
 
     pub async fn gen_new_api_key<T: Signer>(
@@ -192,6 +296,25 @@ impl Api {
         Var: Serialize,
         Res: serde::de::DeserializeOwned,
     {
+        let span = info_span!(
+            "magma_request",
+            op = request_body.operation_name,
+            cost = field::Empty,
+            cost_remaining = field::Empty
+        );
+        self.send_request(request_body).instrument(span).await
+    }
+
+    async fn send_request<Var, Res>(
+        &self,
+        request_body: graphql_client::QueryBody<Var>,
+    ) -> Result<Res, Box<dyn std::error::Error>>
+    where
+        Var: Serialize,
+        Res: serde::de::DeserializeOwned,
+    {
+        self.wait_for_budget().await?;
+
         let client = Client::new();
         let mut request = client.post(Api::API_URL).json(&request_body);
 
@@ -199,36 +322,42 @@ impl Api {
             request = request.header("Authorization", format!("Bearer {}", api_key));
         }
 
-        let res = request.send().await?.json::<Response<Res>>().await?;
+        let res = request.send().await.map_err(magma_error)?;
+        if res.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = res
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok()?.parse().ok())
+                .map(Duration::from_secs);
+            return Err(self.throttled(retry_after).into());
+        }
+        let res = res.json::<Response<Res>>().await.map_err(magma_error)?;
 
         // Process the response
+        self.update_budget(&res.extensions);
+
         if let Some(data) = res.data {
             log_cost(res.extensions);
 
             Ok(data)
         } else if let Some(errors) = res.errors {
             // check if "errors[].extensions.code === FORBIDDEN"
-            if errors.iter().any(|e| {
-                e.extensions.as_ref().map_or(false, |ext| {
-                    ext.get("code")
-                        .and_then(|code| code.as_str())
-                        .map_or(false, |code| code == "FORBIDDEN")
-                })
-            }) {
+            if has_error_code(&errors, "FORBIDDEN") {
                 Err(ForbiddenError {}.into())
             } else {
-                Err(format!("GraphQL error: {:?}", errors).into())
+                Err(MagmaError(format!("GraphQL error: {:?}", errors)).into())
             }
         } else {
-            Err("Unknown error occurred".into())
+            Err(MagmaError("Unknown error occurred".to_string()).into())
         }
     }
 
+    /// Every order ever placed on our offers.
     pub async fn get_orders(
         &self,
     ) -> Result<Vec<OrdersGetUserMarketOfferOrdersList>, Box<dyn std::error::Error>> {
         debug!("GetOrders");
-        let request_body = Orders::build_query(orders::Variables {});
+        let request_body = Orders::build_query(orders::Variables { id: None });
 
         let orders = self
             .request::<orders::Variables, orders::ResponseData>(request_body)
@@ -240,6 +369,78 @@ impl Api {
         Ok(orders)
     }
 
+    pub async fn get_order(
+        &self,
+        id: &str,
+    ) -> Result<Option<OrdersGetUserMarketOfferOrdersList>, Box<dyn std::error::Error>> {
+        debug!("GetOrder");
+        let request_body = Orders::build_query(orders::Variables {
+            id: Some(id.to_string()),
+        });
+
+        let order = self
+            .request::<orders::Variables, orders::ResponseData>(request_body)
+            .await?
+            .get_user
+            .market
+            .and_then(|market| {
+                market
+                    .offer_orders
+                    .list
+                    .into_iter()
+                    .find(|order| order.id == id)
+            });
+
+        Ok(order)
+    }
+
+    /**
+     * Orders on our offers in a status the bot still acts on. Unlike
+     * `get_orders`, the cost of this query doesn't grow with history.
+     */
+    pub async fn get_actionable_orders(
+        &self,
+    ) -> Result<Vec<OrdersGetUserMarketOfferOrdersList>, Box<dyn std::error::Error>> {
+        debug!("ActionableOrders");
+        let request_body = ActionableOrders::build_query(actionable_orders::Variables {});
+
+        let user = self
+            .request::<actionable_orders::Variables, ActionableOrdersData>(request_body)
+            .await?
+            .get_user;
+
+        let mut orders: Vec<_> = user
+            .market
+            .unwrap_or_default()
+            .into_values()
+            .flat_map(|orders| orders.list)
+            // `orders` also lists the ones we placed as a buyer
+            .filter(|order| !user.pubkeys.contains(&order.account))
+            .collect();
+        orders.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+        Ok(orders)
+    }
+
+    /// One page of the orders on our offers, newest first. Magma can't page
+    /// them, so this fetches the full history and pages it here.
+    pub async fn get_order_history(
+        &self,
+        page: usize,
+        per_page: usize,
+    ) -> Result<Vec<OrdersGetUserMarketOfferOrdersList>, Box<dyn std::error::Error>> {
+        self.check_budget()?;
+
+        let mut orders = self.get_orders().await?;
+        orders.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        Ok(orders
+            .into_iter()
+            .skip(page * per_page)
+            .take(per_page)
+            .collect())
+    }
+
     async fn get_sign_info(
         &self,
     ) -> Result<get_sign_info::ResponseData, Box<dyn std::error::Error>> {
@@ -343,6 +544,93 @@ impl Api {
         Ok(addresses)
     }
 
+    pub async fn get_offers(
+        &self,
+    ) -> Result<Vec<market_offers::MarketOffersGetUserMarketOffersList>, Box<dyn std::error::Error>>
+    {
+        debug!("GetOffers");
+        let request_body = MarketOffers::build_query(market_offers::Variables {});
+
+        let offers = self
+            .request::<market_offers::Variables, market_offers::ResponseData>(request_body)
+            .await?
+            .get_user
+            .market
+            .map_or_else(Vec::new, |market| market.offers.list);
+
+        Ok(offers)
+    }
+
+    pub async fn create_offer(
+        &self,
+        input: create_offer::CreateOffer,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        debug!("CreateOffer");
+        let request_body = CreateOffer::build_query(create_offer::Variables { input });
+        self.request::<create_offer::Variables, create_offer::ResponseData>(request_body)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_offer(
+        &self,
+        input: update_offer::UpdateOffer,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        debug!("UpdateOffer");
+        let request_body = UpdateOffer::build_query(update_offer::Variables { input });
+        self.request::<update_offer::Variables, update_offer::ResponseData>(request_body)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn toggle_offer(&self, offer_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        debug!("ToggleOffer");
+        let request_body = ToggleOffer::build_query(toggle_offer::Variables {
+            id: offer_id.to_string(),
+        });
+        self.request::<toggle_offer::Variables, toggle_offer::ResponseData>(request_body)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Writes in the chat of an order.
+    pub async fn send_message(
+        &self,
+        order_id: &str,
+        message: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        debug!("SendMessage");
+        let request_body = SendMessage::build_query(send_message::Variables {
+            order_id: order_id.to_string(),
+            msg: message.to_string(),
+        });
+        let sent = self
+            .request::<send_message::Variables, send_message::ResponseData>(request_body)
+            .await?
+            .send_magma_message;
+        if !sent {
+            Err(format!(
+                "Magma didn't send the message of order {}",
+                order_id
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Unix time at which the API key expires, from its JWT `exp` claim.
+    pub fn api_key_expiry(&self) -> Option<i64> {
+        let api_key = self.config.api_key.as_ref()?;
+        let payload = api_key.split('.').nth(1)?;
+        let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+        let claims: Value = serde_json::from_slice(&payload).ok()?;
+
+        claims.get("exp")?.as_i64()
+    }
+
     pub fn set_api_key(&mut self, new_key: Option<String>) {
         self.config.api_key = new_key;
     }
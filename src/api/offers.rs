@@ -0,0 +1,365 @@
+use log::{debug, info, warn};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::store::OfferAdjustment;
+
+use super::market_offers::{
+    MarketOfferType, MarketOffersGetUserMarketOffersList as Offer, OfferSide, OfferStatus,
+};
+use super::{create_offer, update_offer, Api};
+
+/// A Magma offer as returned by `getUser.market.offers`
+pub type MarketOffer = Offer;
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct OfferConditionConfig {
+    pub condition: String,
+    pub operator: String,
+    pub value: String,
+}
+
+/// A channel sell offer as declared in the config.
+///
+/// Optional fields left unset keep whatever value the offer has on Magma.
+#[derive(Debug, Deserialize, Clone)]
+pub struct OfferConfig {
+    /// Magma id of the offer to manage. If not set, a new offer is created
+    /// unless one with the same parameters already exists.
+    pub id: Option<String>,
+    pub enabled: Option<bool>,
+    pub total_size: f64,
+    pub min_size: Option<f64>,
+    pub max_size: Option<f64>,
+    pub fee_rate: Option<f64>,
+    pub base_fee: Option<f64>,
+    pub fee_rate_cap: Option<f64>,
+    pub base_fee_cap: Option<f64>,
+    pub min_block_length: Option<f64>,
+    pub onchain_multiplier: Option<f64>,
+    pub onchain_priority: Option<String>,
+    pub conditions: Option<Vec<OfferConditionConfig>>,
+}
+
+/// Offer parameters to change. Unset ones are left as they are.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OfferChanges {
+    pub total_size: Option<f64>,
+    pub min_size: Option<f64>,
+    pub max_size: Option<f64>,
+    pub fee_rate: Option<f64>,
+    pub base_fee: Option<f64>,
+    pub onchain_multiplier: Option<f64>,
+}
+
+impl OfferChanges {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl OfferConfig {
+    /// Fails on condition, operator or priority values Magma doesn't know.
+    pub fn validate(&self) -> Result<(), Box<dyn std::error::Error>> {
+        for c in self.conditions.iter().flatten() {
+            known::<create_offer::OfferCondition>(&c.condition)?;
+            known::<create_offer::OfferConditionOperator>(&c.operator)?;
+        }
+        if let Some(priority) = &self.onchain_priority {
+            known::<create_offer::OnchainPriority>(priority)?;
+        }
+        Ok(())
+    }
+}
+
+impl Api {
+    /// Our channel sell offers, the only kind the bot manages.
+    pub async fn get_sell_offers(&self) -> Result<Vec<Offer>, Box<dyn std::error::Error>> {
+        let offers = self
+            .get_offers()
+            .await?
+            .into_iter()
+            .filter(|offer| matches!(offer.side, OfferSide::SELL))
+            .filter(|offer| matches!(offer.offer_type, MarketOfferType::CHANNEL))
+            .collect();
+
+        Ok(offers)
+    }
+
+    /// Changes some parameters of an offer, keeping the others.
+    pub async fn adjust_offer(
+        &self,
+        offer: &Offer,
+        changes: &OfferChanges,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let total_size = match changes.total_size {
+            Some(total_size) => total_size,
+            None => offer.total_size.parse()?,
+        };
+
+        self.update_offer(update_offer::UpdateOffer {
+            base_fee: changes.base_fee,
+            base_fee_cap: None,
+            conditions: None,
+            fee_rate: changes.fee_rate,
+            fee_rate_cap: None,
+            max_size: changes.max_size,
+            min_block_length: None,
+            min_size: changes.min_size,
+            offer: offer.id.clone(),
+            onchain_multiplier: changes.onchain_multiplier,
+            onchain_priority: None,
+            total_size,
+        })
+        .await
+    }
+
+    /**
+     * Makes our Magma sell offers match the ones declared in the config:
+     * 1. Offers with an id are matched to that offer
+     * 2. Offers without an id are created, unless an offer not claimed by
+     *    another entry already has all their parameters
+     * 3. Changed offers are updated, and toggled if `enabled` differs
+     *
+     * Offers on Magma are never taken over by an entry without an id. The
+     * `max_size` and status of offers the liquidity guard shrunk or disabled
+     * are left to the guard, which restores them once funds are back.
+     */
+    pub async fn reconcile_offers(
+        &self,
+        adjustments: &HashMap<String, OfferAdjustment>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(wanted) = &self.config.offers else {
+            return Ok(());
+        };
+
+        let mut current = self.get_sell_offers().await?;
+
+        let (with_id, without_id): (Vec<&OfferConfig>, Vec<&OfferConfig>) =
+            wanted.iter().partition(|offer| offer.id.is_some());
+
+        // 1. Offers with an id are matched to that offer
+        for offer in with_id {
+            let id = offer.id.as_deref().unwrap();
+            match current.iter().position(|current| current.id == id) {
+                Some(index) => {
+                    let current = current.remove(index);
+                    self.sync_offer(offer, &current, adjustments.get(&current.id))
+                        .await?
+                }
+                None => warn!("Offer {} not found on Magma, skipping it", id),
+            }
+        }
+
+        // 2. Offers without an id are created, unless already there
+        for offer in without_id {
+            let matches = |current: &Offer| {
+                let wanted = unguarded(offer, adjustments.get(&current.id));
+                !differs(&wanted, current)
+            };
+            match current.iter().position(matches) {
+                Some(index) => {
+                    let existing = current.remove(index);
+                    info!(
+                        "Offer of {} sats already exists as {}, set its `id` in the config to manage it",
+                        offer.total_size, existing.id
+                    );
+                }
+                None => {
+                    info!("Creating offer of {} sats", offer.total_size);
+                    self.create_offer(create_input(offer)?).await?;
+                }
+            }
+        }
+
+        for offer in current {
+            debug!("Offer {} is not in the config, leaving it as is", offer.id);
+        }
+
+        Ok(())
+    }
+
+    async fn sync_offer(
+        &self,
+        wanted: &OfferConfig,
+        current: &Offer,
+        adjustment: Option<&OfferAdjustment>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let wanted = &unguarded(wanted, adjustment);
+        if differs(wanted, current) {
+            info!("Updating offer {}", current.id);
+            self.update_offer(update_input(wanted, &current.id)?)
+                .await?;
+        } else {
+            debug!("Offer {} is up to date", current.id);
+        }
+
+        let enabled = wanted.enabled.unwrap_or(true);
+        match current.status {
+            OfferStatus::ADMIN_DISABLED => {
+                warn!("Offer {} was disabled by Amboss", current.id);
+            }
+            OfferStatus::ENABLED if !enabled => {
+                info!("Disabling offer {}", current.id);
+                self.toggle_offer(&current.id).await?;
+            }
+            OfferStatus::DISABLED if adjustment.is_some_and(|adjustment| adjustment.disabled) => {
+                debug!(
+                    "Offer {} was disabled for lack of funds, leaving it to the liquidity guard",
+                    current.id
+                );
+            }
+            OfferStatus::DISABLED if enabled => {
+                info!("Enabling offer {}", current.id);
+                self.toggle_offer(&current.id).await?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// The config of an offer without the `max_size` the liquidity guard shrunk.
+fn unguarded(wanted: &OfferConfig, adjustment: Option<&OfferAdjustment>) -> OfferConfig {
+    let mut wanted = wanted.clone();
+    if adjustment.is_some_and(|adjustment| adjustment.original_max_size.is_some()) {
+        wanted.max_size = None;
+    }
+    wanted
+}
+
+fn differs(wanted: &OfferConfig, current: &Offer) -> bool {
+    let changed = |wanted: Option<f64>, current: Option<f64>| wanted.is_some() && wanted != current;
+    let size = |size: &str| size.parse::<f64>().ok();
+
+    let conditions = current.conditions.as_ref().map(|conditions| {
+        conditions
+            .iter()
+            .map(|c| OfferConditionConfig {
+                condition: enum_to_string(&c.condition),
+                operator: enum_to_string(&c.operator),
+                value: c.value.clone(),
+            })
+            .collect::<Vec<_>>()
+    });
+    let onchain_priority = current.onchain_priority.as_ref().map(enum_to_string);
+
+    changed(Some(wanted.total_size), size(&current.total_size))
+        || changed(wanted.min_size, size(&current.min_size))
+        || changed(wanted.max_size, size(&current.max_size))
+        || changed(wanted.fee_rate, Some(current.fee_rate))
+        || changed(wanted.base_fee, Some(current.base_fee))
+        || changed(wanted.fee_rate_cap, current.fee_rate_cap)
+        || changed(wanted.base_fee_cap, current.base_fee_cap)
+        || changed(wanted.min_block_length, Some(current.min_block_length))
+        || changed(wanted.onchain_multiplier, current.onchain_multiplier)
+        || (wanted.onchain_priority.is_some() && wanted.onchain_priority != onchain_priority)
+        || (wanted.conditions.is_some() && wanted.conditions != conditions)
+}
+
+fn create_input(
+    offer: &OfferConfig,
+) -> Result<create_offer::CreateOffer, Box<dyn std::error::Error>> {
+    Ok(create_offer::CreateOffer {
+        base_fee: offer.base_fee,
+        base_fee_cap: offer.base_fee_cap,
+        conditions: conditions(offer)?,
+        fee_rate: offer.fee_rate,
+        fee_rate_cap: offer.fee_rate_cap,
+        max_size: offer.max_size,
+        min_block_length: offer.min_block_length,
+        min_size: offer.min_size,
+        offer_side: Some(create_offer::OfferSide::SELL),
+        offer_type: Some(create_offer::MarketOfferType::CHANNEL),
+        onchain_multiplier: offer.onchain_multiplier,
+        onchain_priority: offer.onchain_priority.as_deref().map(to_enum).transpose()?,
+        total_size: offer.total_size,
+    })
+}
+
+fn update_input(
+    offer: &OfferConfig,
+    offer_id: &str,
+) -> Result<update_offer::UpdateOffer, Box<dyn std::error::Error>> {
+    Ok(update_offer::UpdateOffer {
+        base_fee: offer.base_fee,
+        base_fee_cap: offer.base_fee_cap,
+        conditions: conditions(offer)?,
+        fee_rate: offer.fee_rate,
+        fee_rate_cap: offer.fee_rate_cap,
+        max_size: offer.max_size,
+        min_block_length: offer.min_block_length,
+        min_size: offer.min_size,
+        offer: offer_id.to_string(),
+        onchain_multiplier: offer.onchain_multiplier,
+        onchain_priority: offer.onchain_priority.as_deref().map(to_enum).transpose()?,
+        total_size: offer.total_size,
+    })
+}
+
+/// Converts the conditions of a config offer into one of the generated input types.
+fn conditions<T: DeserializeOwned>(
+    offer: &OfferConfig,
+) -> Result<Option<Vec<T>>, serde_json::Error> {
+    offer
+        .conditions
+        .as_ref()
+        .map(|conditions| {
+            conditions
+                .iter()
+                .map(|c| {
+                    serde_json::from_value(json!({
+                        "condition": c.condition,
+                        "operator": c.operator,
+                        "value": c.value,
+                    }))
+                })
+                .collect()
+        })
+        .transpose()
+}
+
+/// Converts a config string into one of the generated GraphQL enums.
+fn to_enum<T: DeserializeOwned>(value: &str) -> Result<T, serde_json::Error> {
+    serde_json::from_value(Value::String(value.to_string()))
+}
+
+fn enum_to_string<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(value)) => value,
+        _ => String::new(),
+    }
+}
+
+/// Generated GraphQL enums, which take values they don't know as `Other`.
+trait KnownValue {
+    fn is_known(&self) -> bool;
+}
+
+impl KnownValue for create_offer::OfferCondition {
+    fn is_known(&self) -> bool {
+        !matches!(self, Self::Other(_))
+    }
+}
+
+impl KnownValue for create_offer::OfferConditionOperator {
+    fn is_known(&self) -> bool {
+        !matches!(self, Self::Other(_))
+    }
+}
+
+impl KnownValue for create_offer::OnchainPriority {
+    fn is_known(&self) -> bool {
+        !matches!(self, Self::Other(_))
+    }
+}
+
+fn known<T: DeserializeOwned + KnownValue>(value: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !to_enum::<T>(value)?.is_known() {
+        Err(format!("Unknown offer value in the config: {}", value))?;
+    }
+    Ok(())
+}
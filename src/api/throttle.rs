@@ -0,0 +1,144 @@
+use log::{debug, warn};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::time::{sleep, Duration, Instant};
+use tracing::Span;
+
+use crate::errors::RateLimitedError;
+
+use super::Api;
+
+/// Share of the query budget kept for essential queries.
+const RESERVED_SHARE: f64 = 0.2;
+
+/// Longest an essential query waits for the budget to restore.
+const MAX_WAIT: Duration = Duration::from_secs(30);
+
+/// Query cost budget reported by Amboss in `extensions.cost.throttleStatus`.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryBudget {
+    pub maximum: f64,
+    pub available: f64,
+    /// Cost restored per second
+    pub restore_rate: f64,
+    /// Cost of the last query
+    pub last_cost: f64,
+    updated: Instant,
+}
+
+impl QueryBudget {
+    pub fn from_extensions(extensions: &HashMap<String, Value>) -> Option<Self> {
+        let cost = extensions.get("cost")?;
+        let throttle = cost.get("throttleStatus")?;
+
+        Some(QueryBudget {
+            maximum: throttle.get("maximumAvailable")?.as_f64()?,
+            available: throttle.get("currentlyAvailable")?.as_f64()?,
+            restore_rate: throttle.get("restoreRate")?.as_f64()?,
+            last_cost: cost
+                .get("actualQueryCost")
+                .or_else(|| cost.get("requestedQueryCost"))
+                .and_then(Value::as_f64)
+                .unwrap_or_default(),
+            updated: Instant::now(),
+        })
+    }
+
+    /// Budget available now, counting what was restored since the last query.
+    pub fn current(&self) -> f64 {
+        let restored = self.updated.elapsed().as_secs_f64() * self.restore_rate;
+        (self.available + restored).min(self.maximum)
+    }
+
+    /// How long until `cost` is available.
+    pub fn wait_for(&self, cost: f64) -> Duration {
+        let missing = cost - self.current();
+        if missing <= 0.0 {
+            return Duration::ZERO;
+        }
+        if self.restore_rate <= 0.0 {
+            return Duration::MAX;
+        }
+        Duration::from_secs_f64(missing / self.restore_rate)
+    }
+}
+
+impl Api {
+    /// Last query budget reported by Amboss, if any.
+    pub fn query_budget(&self) -> Option<QueryBudget> {
+        self.budget.get()
+    }
+
+    /**
+     * Fails with a `RateLimitedError` when the query budget is down to the
+     * share kept for essential queries. Callers of non-essential queries
+     * check it first and skip them.
+     */
+    pub fn check_budget(&self) -> Result<(), RateLimitedError> {
+        let Some(budget) = self.budget.get() else {
+            return Ok(());
+        };
+
+        let reserved = budget.maximum * RESERVED_SHARE;
+        match budget.wait_for(reserved + budget.last_cost) {
+            Duration::ZERO => Ok(()),
+            retry_after => Err(RateLimitedError { retry_after }),
+        }
+    }
+
+    /// Waits until the budget covers another query like the last one.
+    pub(super) async fn wait_for_budget(&self) -> Result<(), RateLimitedError> {
+        let Some(budget) = self.budget.get() else {
+            return Ok(());
+        };
+
+        let wait = budget.wait_for(budget.last_cost);
+        if wait > MAX_WAIT {
+            return Err(RateLimitedError { retry_after: wait });
+        }
+        if !wait.is_zero() {
+            debug!(
+                "Waiting {:.1}s for the query budget to restore",
+                wait.as_secs_f64()
+            );
+            sleep(wait).await;
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn update_budget(&self, extensions: &Option<HashMap<String, Value>>) {
+        let Some(budget) = extensions.as_ref().and_then(QueryBudget::from_extensions) else {
+            return;
+        };
+
+        // Fields of the `magma_request` span
+        Span::current()
+            .record("cost", budget.last_cost)
+            .record("cost_remaining", budget.available);
+
+        if budget.available < budget.maximum * RESERVED_SHARE {
+            warn!(
+                "Amboss query budget running low: {} of {} left",
+                budget.available, budget.maximum
+            );
+        }
+        self.budget.set(Some(budget));
+    }
+
+    /**
+     * The rate limit error matching an HTTP 429 response, waiting as long as
+     * its `Retry-After` header says, or else until the budget restores.
+     */
+    pub(super) fn throttled(&self, retry_after: Option<Duration>) -> RateLimitedError {
+        let retry_after = retry_after.unwrap_or_else(|| {
+            self.budget
+                .get()
+                .map(|budget| budget.wait_for(budget.last_cost.max(1.0)))
+                .filter(|wait| !wait.is_zero() && *wait != Duration::MAX)
+                .unwrap_or(MAX_WAIT)
+        });
+
+        RateLimitedError { retry_after }
+    }
+}
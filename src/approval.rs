@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+use crate::errors::BotUnavailableError;
+
+/// Which new orders wait for the operator instead of being accepted right away.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ApprovalConfig {
+    /// Orders of at least this many sats wait for approval
+    pub min_size: Option<i64>,
+    /// Orders from buyers we never sold a channel to wait for approval. Default is false
+    pub unknown_buyers: Option<bool>,
+    /// Seconds before the order times out at which the bot decides by itself. Default is 300
+    pub decide_before: Option<u64>,
+    /// What the bot decides then. Default is reject
+    pub on_timeout: Option<Decision>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Approve,
+    Reject,
+}
+
+impl ApprovalConfig {
+    /// Why an order needs the operator's approval, if it does.
+    pub fn reason(&self, size: i64, known_buyer: bool) -> Option<String> {
+        if let Some(min_size) = self.min_size.filter(|min_size| size >= *min_size) {
+            return Some(format!("{} sats is at least {} sats", size, min_size));
+        }
+        if self.unknown_buyers.unwrap_or(false) && !known_buyer {
+            return Some("we never sold a channel to this buyer".to_string());
+        }
+        None
+    }
+
+    pub fn decide_before(&self) -> u64 {
+        self.decide_before.unwrap_or(300)
+    }
+
+    pub fn on_timeout(&self) -> Decision {
+        self.on_timeout.unwrap_or(Decision::Reject)
+    }
+}
+
+/// An order parked until the operator decides.
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingApproval {
+    pub order_id: String,
+    pub buyer: String,
+    pub size: i64,
+    pub reason: String,
+    /// Unix time at which the order was parked
+    pub requested_at: u64,
+    /// Unix time at which the bot decides by itself
+    pub deadline: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    pending: HashMap<String, PendingApproval>,
+    decisions: Option<mpsc::UnboundedSender<(String, Decision)>>,
+}
+
+/// Orders waiting for the operator, filled by the service and decided through
+/// the HTTP server. Cloning shares the same state.
+#[derive(Debug, Clone, Default)]
+pub struct Approvals {
+    state: Arc<Mutex<State>>,
+}
+
+impl Approvals {
+    /// Decisions taken by the operator, as `(order id, decision)`.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<(String, Decision)> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.state.lock().unwrap().decisions = Some(tx);
+        rx
+    }
+
+    pub fn park(&self, pending: PendingApproval) {
+        self.state
+            .lock()
+            .unwrap()
+            .pending
+            .insert(pending.order_id.clone(), pending);
+    }
+
+    pub fn remove(&self, order_id: &str) {
+        self.state.lock().unwrap().pending.remove(order_id);
+    }
+
+    /// Orders waiting for the operator, oldest first.
+    pub fn pending(&self) -> Vec<PendingApproval> {
+        let mut pending: Vec<_> = self
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .values()
+            .cloned()
+            .collect();
+        pending.sort_by_key(|pending| pending.requested_at);
+        pending
+    }
+
+    /// Hands the operator's decision to the service, if the order is waiting for one.
+    pub fn decide(
+        &self,
+        order_id: &str,
+        decision: Decision,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut state = self.state.lock().unwrap();
+        let decisions = state
+            .decisions
+            .clone()
+            .ok_or(BotUnavailableError("The bot isn't running"))?;
+        if state.pending.remove(order_id).is_none() {
+            return Err(format!("Order {} isn't waiting for approval", order_id).into());
+        }
+
+        decisions
+            .send((order_id.to_string(), decision))
+            .map_err(|_| BotUnavailableError("The bot stopped").into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pending(order_id: &str) -> PendingApproval {
+        PendingApproval {
+            order_id: order_id.to_string(),
+            buyer: "buyer".to_string(),
+            size: 1_000_000,
+            reason: "size".to_string(),
+            requested_at: 0,
+            deadline: None,
+        }
+    }
+
+    #[test]
+    fn decide_keeps_order_parked_without_bot() {
+        let approvals = Approvals::default();
+        approvals.park(pending("a"));
+
+        let e = approvals.decide("a", Decision::Approve).unwrap_err();
+        assert!(e.is::<BotUnavailableError>());
+        assert_eq!(approvals.pending().len(), 1);
+    }
+
+    #[test]
+    fn decide_hands_decision_to_bot() {
+        let approvals = Approvals::default();
+        let mut decisions = approvals.subscribe();
+        approvals.park(pending("a"));
+
+        approvals.decide("a", Decision::Reject).unwrap();
+        assert!(approvals.pending().is_empty());
+        assert!(matches!(
+            decisions.try_recv(),
+            Ok((id, Decision::Reject)) if id == "a"
+        ));
+
+        let e = approvals.decide("a", Decision::Reject).unwrap_err();
+        assert!(!e.is::<BotUnavailableError>());
+    }
+}
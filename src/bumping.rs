@@ -0,0 +1,23 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct FeeBumpingConfig {
+    /// Blocks a funding transaction may stay unconfirmed before bumping it. Default is 3
+    pub after_blocks: Option<u32>,
+    /// Highest fee rate (sat/vB) to bump to
+    pub max_fee_rate: u64,
+    /// Most sats to spend on fees for a bump. Default is LND's, half the bumped output
+    pub budget: Option<u64>,
+}
+
+impl FeeBumpingConfig {
+    /// Whether a transaction broadcast at `broadcast_height` waited long enough.
+    pub fn is_due(&self, broadcast_height: u32, height: u32) -> bool {
+        height.saturating_sub(broadcast_height) >= self.after_blocks.unwrap_or(3)
+    }
+
+    /// Fee rate to bump to, given the current fastest fee.
+    pub fn target_fee_rate(&self, fastest_fee: u64) -> u64 {
+        fastest_fee.min(self.max_fee_rate)
+    }
+}
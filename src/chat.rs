@@ -0,0 +1,37 @@
+use serde::Deserialize;
+
+/// Automatic messages written in the chat of orders. Templates may use
+/// `{order_id}`, `{size}` and, depending on the message, `{txid}`,
+/// `{channel_point}` or `{reason}`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ChatConfig {
+    /// Forward buyers' messages to the notification sinks. Default is true
+    pub forward: Option<bool>,
+    /// Sent once the channel is opened and confirmed to Magma
+    pub channel_opened: Option<String>,
+    /// Sent before rejecting an order
+    pub rejected: Option<String>,
+}
+
+impl ChatConfig {
+    pub fn channel_opened(&self) -> &str {
+        self.channel_opened
+            .as_deref()
+            .unwrap_or("Channel opened, txid {txid}. Thanks for your order!")
+    }
+
+    pub fn rejected(&self) -> &str {
+        self.rejected
+            .as_deref()
+            .unwrap_or("Sorry, we can't take this order: {reason}")
+    }
+}
+
+/// Fills the `{name}` placeholders of a template.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    values
+        .iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
@@ -0,0 +1,117 @@
+use clap::{Parser, Subcommand, ValueEnum};
+
+use crate::api::cancel_order::OrderCancellationReason;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Sells channels on Amboss Magma from an LND node")]
+pub struct Cli {
+    /// What to do. Default is `run`
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the order loop
+    Run,
+    /// Browse the orders on our offers
+    Orders {
+        #[command(subcommand)]
+        command: OrdersCommand,
+    },
+    /// Look at or act on a single order
+    Order {
+        #[command(subcommand)]
+        command: OrderCommand,
+    },
+    /// Decide on the orders the running bot parked for approval
+    Approvals {
+        #[command(subcommand)]
+        command: ApprovalsCommand,
+    },
+    /// Log in to Magma with the node and save a new API key
+    Login,
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Show the current fee rate and what opening a channel would cost
+    Fees {
+        /// Channel size to fund, in sats. Default is a single input
+        #[arg(long)]
+        size: Option<i64>,
+    },
+    /// List sold channels still under their minimum lease
+    Leases,
+    /// Cooperatively close a channel, refusing while it's under lease
+    CloseChannel {
+        /// Channel point, as `txid:output_index`
+        channel_point: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum OrdersCommand {
+    /// List orders, newest first
+    List {
+        /// Only orders in this Magma status, e.g. WAITING_FOR_SELLER_APPROVAL
+        #[arg(long)]
+        status: Option<String>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum OrderCommand {
+    /// Show an order with what the bot recorded about it
+    Show { id: String },
+    /// Accept an order waiting for approval, as the loop would
+    Accept { id: String },
+    /// Reject an order waiting for approval
+    Reject { id: String },
+    /// Cancel an accepted order
+    Cancel {
+        id: String,
+        #[arg(long, value_enum, default_value_t = CancelReason::UnableToPay)]
+        reason: CancelReason,
+    },
+    /// Open the channel of a paid order and confirm it to Magma
+    OpenChannel { id: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ApprovalsCommand {
+    /// List the orders waiting for approval
+    List,
+    /// Let the bot accept a parked order
+    Approve { id: String },
+    /// Let the bot reject a parked order
+    Reject { id: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ConfigCommand {
+    /// Load the config and check LND, Magma and the notification sinks
+    Check,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum CancelReason {
+    ChannelSizeOutOfBounds,
+    UnableToConnectToNode,
+    UnableToPay,
+}
+
+impl From<CancelReason> for OrderCancellationReason {
+    fn from(reason: CancelReason) -> Self {
+        match reason {
+            CancelReason::ChannelSizeOutOfBounds => {
+                OrderCancellationReason::CHANNEL_SIZE_OUT_OF_BOUNDS
+            }
+            CancelReason::UnableToConnectToNode => {
+                OrderCancellationReason::UNABLE_TO_CONNECT_TO_NODE
+            }
+            CancelReason::UnableToPay => OrderCancellationReason::UNABLE_TO_PAY,
+        }
+    }
+}
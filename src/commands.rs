@@ -0,0 +1,351 @@
+use serde_json::json;
+
+use crate::api::cancel_order::OrderCancellationReason;
+use crate::api::{Api, MagmaConfig};
+use crate::cli::{ApprovalsCommand, OrderCommand};
+use crate::config;
+use crate::errors::LeaseActiveError;
+use crate::lease;
+use crate::node::LNNode;
+use crate::notify::Notifications;
+use crate::service::open_cost;
+use crate::store::Store;
+
+/// Lists the sold channels that are still under lease.
+pub async fn leases() -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load()?;
+    let node = LNNode::new(config.lnd).await?;
+    let store = Store::load()?;
+
+    let height = node.get_info().await?.block_height;
+    let channels = lease::channels_under_lease(&store, height);
+
+    if channels.is_empty() {
+        println!("No channels under lease at height {}", height);
+        return Ok(());
+    }
+
+    for channel in channels {
+        let remaining = match channel.lease_end_height {
+            Some(end) => format!("until height {} ({} blocks)", end, end - height),
+            None => "until confirmed + lease".to_string(),
+        };
+        println!(
+            "{}  order {}  {} sats  {}",
+            channel.channel_point, channel.order_id, channel.size, remaining
+        );
+    }
+
+    Ok(())
+}
+
+/// Cooperatively closes a channel, refusing if it was sold and is still under
+/// lease (unless `guard_leases` is disabled).
+pub async fn close_channel(channel_point: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load()?;
+    let node = LNNode::new(config.lnd).await?;
+
+    if config.guard_leases.unwrap_or(true) {
+        let store = Store::load()?;
+        let height = node.get_info().await?.block_height;
+        lease::check_can_close(&store, channel_point, height)?;
+    }
+
+    let closing_txid = node.close_channel(channel_point).await?;
+    println!("Closing transaction: {}", closing_txid);
+
+    Ok(())
+}
+
+/// Magma API client with the configured or saved key, without logging in.
+fn connect_api(config: MagmaConfig) -> Result<Api, Box<dyn std::error::Error>> {
+    let missing_api_key = config.api_key.is_none();
+    let mut api = Api::new(config);
+
+    if missing_api_key {
+        api.load_api_key_from_file()
+            .map_err(|_| "No Magma API key, run `login` first")?;
+    }
+    Ok(api)
+}
+
+/// Lists the orders on our offers, newest first.
+pub async fn orders_list(status: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load()?;
+    let api = connect_api(config.magma)?;
+
+    let mut orders = api.get_orders().await?;
+    orders.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    for order in orders {
+        let order_status = format!("{:?}", order.status);
+        if status.is_some_and(|status| !status.eq_ignore_ascii_case(&order_status)) {
+            continue;
+        }
+        println!(
+            "{}  {}  {}  {} sats  {}",
+            order.id, order.created_at, order_status, order.size, order.account
+        );
+    }
+
+    Ok(())
+}
+
+/// Prints an order with what the bot recorded about it.
+pub async fn order_show(order_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load()?;
+    let api = connect_api(config.magma)?;
+    let store = Store::load()?;
+
+    let order = api
+        .get_order(order_id)
+        .await?
+        .ok_or_else(|| format!("Order {} not found", order_id))?;
+
+    let details = json!({
+        "order": order,
+        "record": store.orders.get(order_id),
+        "reservation": store.reservations.get(order_id),
+        "channel": store.channels.get(order_id),
+    });
+    println!("{}", serde_json::to_string_pretty(&details)?);
+
+    Ok(())
+}
+
+/**
+ * Looks at or acts on an order. Actions are taken by the running bot, reached
+ * on its control API, so only one process writes the state file.
+ */
+pub async fn order(command: OrderCommand) -> Result<(), Box<dyn std::error::Error>> {
+    if let OrderCommand::Show { id } = &command {
+        return order_show(id).await;
+    }
+    let bot = Bot::connect()?;
+
+    let (request, done) = match command {
+        OrderCommand::Show { .. } => unreachable!(),
+        OrderCommand::Accept { id } => (
+            bot.client
+                .post(bot.url(&format!("api/orders/{}/accept", id))),
+            format!("Order {} accepted", id),
+        ),
+        OrderCommand::Reject { id } => (
+            bot.client
+                .post(bot.url(&format!("api/orders/{}/reject", id))),
+            format!("Order {} rejected", id),
+        ),
+        OrderCommand::Cancel { id, reason } => (
+            bot.client
+                .post(bot.url(&format!("api/orders/{}/cancel", id)))
+                .query(&[("reason", OrderCancellationReason::from(reason))]),
+            format!("Order {} cancelled", id),
+        ),
+        OrderCommand::OpenChannel { id } => (
+            bot.client
+                .post(bot.url(&format!("api/orders/{}/open-channel", id))),
+            format!("Channel of order {} opened and confirmed", id),
+        ),
+    };
+
+    bot.send(request).await?;
+    println!("{}", done);
+
+    Ok(())
+}
+
+/// Client of the running bot's HTTP server.
+struct Bot {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl Bot {
+    /// Fails without `api_token`, the bot only serves operators with one.
+    fn connect() -> Result<Self, Box<dyn std::error::Error>> {
+        let config = config::load()?;
+        let http = config
+            .http
+            .ok_or("The bot is reached on its HTTP server, set the `http` section of the config")?;
+        let token = http.api_token.ok_or(
+            "The bot is operated with `api_token`, set it in the `http` section of the config",
+        )?;
+        let listen = http.listen.unwrap_or_else(|| "127.0.0.1:9090".to_string());
+
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            format!("Bearer {}", token).parse()?,
+        );
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()?;
+
+        Ok(Self {
+            client,
+            base_url: format!("http://{}", listen),
+        })
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    /// Answer of the bot, or the error it refused the request with.
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let res = request.send().await?;
+        let status = res.status();
+        let body: serde_json::Value = res.json().await.unwrap_or_default();
+        if !status.is_success() {
+            Err(body["error"]
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("The bot refused the request: {}", status)))?;
+        }
+        Ok(body)
+    }
+}
+
+/// Lists or decides on the orders parked for approval, on the running bot.
+pub async fn approvals(command: ApprovalsCommand) -> Result<(), Box<dyn std::error::Error>> {
+    let bot = Bot::connect()?;
+
+    let (request, done) = match command {
+        ApprovalsCommand::List => {
+            let pending = bot.send(bot.client.get(bot.url("approvals"))).await?;
+            let pending = pending.as_array().cloned().unwrap_or_default();
+            if pending.is_empty() {
+                println!("No order waiting for approval");
+            }
+            for order in pending {
+                let deadline = match order["deadline"].as_u64() {
+                    Some(deadline) => format!("bot decides at {}", deadline),
+                    None => "no deadline".to_string(),
+                };
+                println!(
+                    "{}  {} sats  {}  {}  {}",
+                    order["order_id"].as_str().unwrap_or_default(),
+                    order["size"],
+                    order["buyer"].as_str().unwrap_or_default(),
+                    order["reason"].as_str().unwrap_or_default(),
+                    deadline
+                );
+            }
+            return Ok(());
+        }
+        ApprovalsCommand::Approve { id } => (
+            bot.client
+                .post(bot.url(&format!("approvals/{}/approve", id))),
+            format!("Order {} approved", id),
+        ),
+        ApprovalsCommand::Reject { id } => (
+            bot.client
+                .post(bot.url(&format!("approvals/{}/reject", id))),
+            format!("Order {} rejected", id),
+        ),
+    };
+
+    bot.send(request).await?;
+    println!("{}", done);
+
+    Ok(())
+}
+
+/// Logs in to Magma with the node and saves a new API key.
+pub async fn login() -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load()?;
+    let node = LNNode::new(config.lnd).await?;
+    let mut api = Api::new(config.magma);
+
+    api.gen_new_api_key(&node).await?;
+    match api.api_key_expiry() {
+        Some(expiry) => println!("New API key saved, valid until {}", expiry),
+        None => println!("New API key saved"),
+    }
+
+    Ok(())
+}
+
+/**
+ * Checks everything the bot needs:
+ * 1. The config file parses
+ * 2. LND answers
+ * 3. The Magma API key works
+ * 4. The notification sinks are valid
+ */
+pub async fn config_check() -> Result<(), Box<dyn std::error::Error>> {
+    // 1. The config file parses
+    let config = config::load()?;
+    println!("config: ok");
+
+    let mut failed = false;
+    let mut report = |name: &str, result: Result<String, Box<dyn std::error::Error>>| match result {
+        Ok(detail) => println!("{}: ok, {}", name, detail),
+        Err(e) => {
+            println!("{}: FAILED, {}", name, e);
+            failed = true;
+        }
+    };
+
+    // 2. LND answers
+    let lnd = async {
+        let node = LNNode::new(config.lnd).await?;
+        let info = node.get_info().await?;
+        Ok(format!(
+            "{} at height {}, synced to chain: {}",
+            info.alias, info.block_height, info.synced_to_chain
+        ))
+    }
+    .await;
+    report("lnd", lnd);
+
+    // 3. The Magma API key works
+    let magma = async {
+        let api = connect_api(config.magma)?;
+        let offers = api.get_offers().await?;
+        Ok(match api.api_key_expiry() {
+            Some(expiry) => format!("{} offers, API key valid until {}", offers.len(), expiry),
+            None => format!("{} offers", offers.len()),
+        })
+    }
+    .await;
+    report("magma", magma);
+
+    // 4. The notification sinks are valid
+    let sinks = config.notifications.unwrap_or_default();
+    let count = sinks.len();
+    report(
+        "notifications",
+        Notifications::new(sinks).map(|_| format!("{} sinks", count)),
+    );
+
+    if failed {
+        Err("Some checks failed")?;
+    }
+    Ok(())
+}
+
+/// Shows the current fee rate and what opening a channel would cost.
+pub async fn fees(channel_size: Option<i64>) -> Result<(), Box<dyn std::error::Error>> {
+    let config = config::load()?;
+    let node = LNNode::new(config.lnd).await?;
+    let cost = open_cost(&node, channel_size).await?;
+
+    println!("Fastest fee: {} sat/vB", cost.sat_per_vbyte);
+    println!("Spendable: {} sats", cost.spendable);
+    match channel_size {
+        Some(size) => println!(
+            "Opening a {} sats channel: {} inputs, {:.0} sats in fees",
+            size, cost.inputs, cost.fee
+        ),
+        None => println!(
+            "Opening a channel from a single input: {:.0} sats in fees",
+            cost.fee
+        ),
+    }
+
+    Ok(())
+}
@@ -5,21 +5,61 @@ use std::error::Error;
 use std::fs;
 
 use crate::api::MagmaConfig;
+use crate::approval::ApprovalConfig;
+use crate::bumping::FeeBumpingConfig;
+use crate::chat::ChatConfig;
+use crate::http::HttpConfig;
 use crate::node::LNDConfig;
+use crate::notify::NotificationConfig;
+use crate::pricing::PricingConfig;
+use crate::profitability::ProfitabilityConfig;
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
     pub loop_interval: Option<u64>,
+    pub guard_leases: Option<bool>,
+    pub enforce_fee_caps: Option<bool>,
+    pub base_fee_cap_unit: Option<BaseFeeUnit>,
+    pub pricing: Option<PricingConfig>,
+    pub guard_liquidity: Option<bool>,
+    pub profitability: Option<ProfitabilityConfig>,
+    pub fee_bumping: Option<FeeBumpingConfig>,
+    pub http: Option<HttpConfig>,
+    pub notifications: Option<Vec<NotificationConfig>>,
+    pub chat: Option<ChatConfig>,
+    pub approval: Option<ApprovalConfig>,
     pub lnd: LNDConfig,
     pub magma: MagmaConfig,
 }
 
+/// Unit of the `locked_base_fee_cap` of orders, which the Magma schema doesn't state.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum BaseFeeUnit {
+    /// Same unit as LND's `fee_base_msat`
+    #[default]
+    Msat,
+    Sat,
+}
+
+impl BaseFeeUnit {
+    pub fn to_msat(self, base_fee: i64) -> i64 {
+        match self {
+            BaseFeeUnit::Msat => base_fee,
+            BaseFeeUnit::Sat => base_fee * 1000,
+        }
+    }
+}
+
 pub fn load() -> Result<Config, Box<dyn Error>> {
     let path = env::var("CONFIG_PATH").unwrap_or_else(|_| "config.yaml".to_string());
     debug!("Loading config from {}", fs::canonicalize(&path)?.display());
 
     let contents = fs::read_to_string(path)?;
     let config: Config = serde_yaml::from_str(&contents)?;
+    for offer in config.magma.offers.iter().flatten() {
+        offer.validate()?;
+    }
     debug!("Config loaded: {:?}", config);
     Ok(config)
 }
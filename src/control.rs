@@ -0,0 +1,95 @@
+use serde_json::Value;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::api::cancel_order::OrderCancellationReason;
+use crate::errors::BotUnavailableError;
+
+/// What the control API asks the service.
+#[derive(Debug)]
+pub enum Request {
+    /// Actionable orders with what the bot recorded about them
+    Orders,
+    /// Wallet funds, spendable and reserved for orders
+    Liquidity,
+    /// Current fee rate and cost of opening a channel of that size
+    Fees {
+        size: Option<i64>,
+    },
+    /// Run an order through the loop now
+    Reprocess {
+        order_id: String,
+    },
+    Accept {
+        order_id: String,
+    },
+    Reject {
+        order_id: String,
+    },
+    /// Cancel an accepted order
+    Cancel {
+        order_id: String,
+        reason: OrderCancellationReason,
+    },
+    /// Open the channel of a paid order and confirm it
+    OpenChannel {
+        order_id: String,
+    },
+    /// Pause or resume order processing, until told otherwise
+    SetPaused {
+        paused: bool,
+    },
+}
+
+/// A request with where to send its answer.
+pub struct Command {
+    pub request: Request,
+    pub reply: oneshot::Sender<Result<Value, String>>,
+}
+
+/// Link between the control API and the service. Cloning shares the same state.
+#[derive(Debug, Clone, Default)]
+pub struct Control {
+    commands: Arc<Mutex<Option<mpsc::UnboundedSender<Command>>>>,
+    paused: Arc<AtomicBool>,
+}
+
+impl Control {
+    /// Requests from the control API, answered by the service between polls.
+    pub fn subscribe(&self) -> mpsc::UnboundedReceiver<Command> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        *self.commands.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    /// Hands a request to the service and waits for its answer.
+    pub async fn ask(&self, request: Request) -> Result<Value, Box<dyn std::error::Error>> {
+        let (reply, answer) = oneshot::channel();
+        self.commands
+            .lock()
+            .unwrap()
+            .as_ref()
+            .ok_or(BotUnavailableError("The bot isn't running"))?
+            .send(Command { request, reply })
+            .map_err(|_| BotUnavailableError("The bot stopped"))?;
+
+        let answer = answer
+            .await
+            .map_err(|_| BotUnavailableError("The bot dropped the request"))?;
+        Ok(answer?)
+    }
+
+    /**
+     * Pausing stops the loop and the channel openings of orders paid in the
+     * meantime, which wait for processing to resume. Requests of the operator,
+     * through the control API or approvals, are still carried out.
+     */
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
@@ -6,3 +6,77 @@ impl std::fmt::Display for ForbiddenError {
     }
 }
 impl std::error::Error for ForbiddenError {}
+
+#[derive(Debug)]
+pub struct LeaseActiveError {
+    pub order_id: String,
+    pub lease_end_height: Option<u32>,
+}
+impl std::fmt::Display for LeaseActiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.lease_end_height {
+            Some(height) => write!(
+                f,
+                "Channel sold in order {} is under lease until height {}",
+                self.order_id, height
+            ),
+            None => write!(
+                f,
+                "Channel sold in order {} is under lease, waiting for confirmation",
+                self.order_id
+            ),
+        }
+    }
+}
+impl std::error::Error for LeaseActiveError {}
+
+#[derive(Debug)]
+pub struct InsufficientFundsError {
+    pub needed: i64,
+    pub available: i64,
+}
+impl std::fmt::Display for InsufficientFundsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Not enough funds: {} sats needed, {} sats available",
+            self.needed, self.available
+        )
+    }
+}
+impl std::error::Error for InsufficientFundsError {}
+
+#[derive(Debug)]
+pub struct RateLimitedError {
+    pub retry_after: std::time::Duration,
+}
+impl std::fmt::Display for RateLimitedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Amboss query budget exhausted, retry in {:.0}s",
+            self.retry_after.as_secs_f64().ceil()
+        )
+    }
+}
+impl std::error::Error for RateLimitedError {}
+
+/// The Magma API answered with an error, or couldn't be reached.
+#[derive(Debug)]
+pub struct MagmaError(pub String);
+impl std::fmt::Display for MagmaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl std::error::Error for MagmaError {}
+
+/// The HTTP server can't reach the service, which isn't running or stopped.
+#[derive(Debug)]
+pub struct BotUnavailableError(pub &'static str);
+impl std::fmt::Display for BotUnavailableError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+impl std::error::Error for BotUnavailableError {}
@@ -0,0 +1,150 @@
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+use crate::clock::now;
+
+/// Last known state of something the bot depends on.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Dependency {
+    pub ok: bool,
+    pub detail: Option<String>,
+    /// Unix time of the check
+    pub checked_at: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    lnd: Dependency,
+    magma: Dependency,
+    /// Unix time of the last loop that went through
+    last_success: Option<u64>,
+    /// Unix time at which the operator paused the loop
+    paused_since: Option<u64>,
+}
+
+/// Health of the bot, updated by the service and read by `/readyz`.
+/// Cloning shares the same state.
+#[derive(Debug, Clone)]
+pub struct Health {
+    state: Arc<Mutex<State>>,
+    /// Seconds without a successful loop before the bot isn't ready anymore
+    max_age: u64,
+}
+
+impl Health {
+    pub fn new(interval: u64, max_intervals: u64) -> Self {
+        Health {
+            state: Arc::default(),
+            max_age: interval * max_intervals,
+        }
+    }
+
+    pub fn set_lnd(&self, result: Result<String, String>) {
+        self.state.lock().unwrap().lnd = dependency(result);
+    }
+
+    pub fn set_magma(&self, result: Result<String, String>) {
+        self.state.lock().unwrap().magma = dependency(result);
+    }
+
+    pub fn run_succeeded(&self) {
+        self.state.lock().unwrap().last_success = Some(now());
+    }
+
+    /// Stops the last run from aging while the operator holds the loop.
+    pub fn set_paused(&self, paused: bool) {
+        let mut state = self.state.lock().unwrap();
+        match (paused, state.paused_since) {
+            (true, None) => state.paused_since = Some(now()),
+            (false, Some(since)) => {
+                let paused_for = now().saturating_sub(since);
+                state.last_success = state.last_success.map(|at| at + paused_for);
+                state.paused_since = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether every dependency is fine, with the details of each.
+    pub fn readiness(&self) -> (bool, Value) {
+        let state = self.state.lock().unwrap();
+        let paused = state.paused_since.is_some();
+        let age = state
+            .last_success
+            .map(|at| state.paused_since.unwrap_or_else(now).saturating_sub(at));
+        // A bot paused before its first loop isn't late either
+        let recent = match age {
+            Some(age) => age <= self.max_age,
+            None => paused,
+        };
+
+        let ready = state.lnd.ok && state.magma.ok && recent;
+        let details = json!({
+            "ready": ready,
+            "lnd": state.lnd,
+            "magma": state.magma,
+            "last_run": {
+                "ok": recent,
+                "last_success": state.last_success,
+                "age": age,
+                "max_age": self.max_age,
+                "paused": paused,
+            },
+        });
+
+        (ready, details)
+    }
+}
+
+fn dependency(result: Result<String, String>) -> Dependency {
+    let (ok, detail) = match result {
+        Ok(detail) => (true, detail),
+        Err(detail) => (false, detail),
+    };
+
+    Dependency {
+        ok,
+        detail: Some(detail),
+        checked_at: Some(now()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn healthy() -> Health {
+        let health = Health::new(60, 3);
+        health.set_lnd(Ok("synced".to_string()));
+        health.set_magma(Ok("key valid".to_string()));
+        health
+    }
+
+    #[test]
+    fn paused_before_first_loop_is_ready() {
+        let health = healthy();
+        assert!(!health.readiness().0);
+
+        health.set_paused(true);
+        let (ready, details) = health.readiness();
+        assert!(ready);
+        assert_eq!(details["last_run"]["paused"], true);
+    }
+
+    #[test]
+    fn pause_doesnt_age_last_run() {
+        let health = healthy();
+        let start = now();
+        health.state.lock().unwrap().last_success = Some(start - 1050);
+        health.state.lock().unwrap().paused_since = Some(start - 1000);
+        let (ready, details) = health.readiness();
+        assert!(ready);
+        assert_eq!(details["last_run"]["age"], 50);
+
+        health.set_paused(false);
+        let state = health.state.lock().unwrap();
+        assert!(state.paused_since.is_none());
+        assert!(state.last_success.unwrap() >= start - 50);
+    }
+}
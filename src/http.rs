@@ -0,0 +1,288 @@
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use subtle::ConstantTimeEq;
+
+use crate::api::cancel_order::OrderCancellationReason;
+use crate::approval::{Approvals, Decision};
+use crate::control::{self, Control};
+use crate::errors::BotUnavailableError;
+use crate::health::Health;
+use crate::metrics::Metrics;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HttpConfig {
+    /// Address to listen on. Default is 127.0.0.1:9090
+    pub listen: Option<String>,
+    /// Serve Prometheus metrics on `/metrics`. Default is true
+    pub metrics: Option<bool>,
+    /// Loop intervals without a successful run before `/readyz` fails. Default is 3
+    pub ready_intervals: Option<u64>,
+    /// Bearer token required by the control API on `/api` and by `/approvals`,
+    /// which are only served with one
+    pub api_token: Option<String>,
+}
+
+#[derive(Clone)]
+struct AppState {
+    metrics: Metrics,
+    health: Health,
+    approvals: Approvals,
+    control: Control,
+    api_token: Option<String>,
+}
+
+/// Serves the bot's HTTP endpoints until the process exits.
+pub async fn serve(
+    config: HttpConfig,
+    metrics: Metrics,
+    health: Health,
+    approvals: Approvals,
+    control: Control,
+) {
+    let listen = config
+        .listen
+        .unwrap_or_else(|| "127.0.0.1:9090".to_string());
+    let state = AppState {
+        metrics,
+        health,
+        approvals,
+        control,
+        api_token: config.api_token.clone(),
+    };
+
+    // Decisions and the control API are only served with a token to require
+    let mut operator = Router::new();
+    match &config.api_token {
+        Some(_) => {
+            operator = operator
+                .route("/approvals", get(get_approvals))
+                .route("/approvals/{id}/approve", post(approve))
+                .route("/approvals/{id}/reject", post(reject))
+                .nest(
+                    "/api",
+                    Router::new()
+                        .route("/orders", get(get_orders))
+                        .route("/orders/{id}/reprocess", post(reprocess_order))
+                        .route("/orders/{id}/accept", post(accept_order))
+                        .route("/orders/{id}/reject", post(reject_order))
+                        .route("/orders/{id}/cancel", post(cancel_order))
+                        .route("/orders/{id}/open-channel", post(open_channel))
+                        .route("/approvals", get(get_approvals))
+                        .route("/liquidity", get(get_liquidity))
+                        .route("/fees", get(get_fees))
+                        .route("/status", get(get_status))
+                        .route("/pause", post(pause))
+                        .route("/resume", post(resume)),
+                )
+                .layer(middleware::from_fn_with_state(state.clone(), authorize));
+        }
+        None => warn!("No `api_token` set, approvals and the control API are not served"),
+    }
+
+    let mut app = Router::new()
+        .route("/healthz", get(get_health))
+        .route("/readyz", get(get_readiness))
+        .merge(operator);
+    if config.metrics.unwrap_or(true) {
+        app = app.route("/metrics", get(get_metrics));
+    }
+    let app = app.with_state(state);
+
+    let listener = match tokio::net::TcpListener::bind(&listen).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Error listening on {}: {}", listen, e);
+            return;
+        }
+    };
+    info!("HTTP server listening on {}", listen);
+
+    if let Err(e) = axum::serve(listener, app).await {
+        error!("HTTP server stopped: {}", e);
+    }
+}
+
+async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    match state.metrics.render() {
+        Ok(body) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)],
+            body,
+        )
+            .into_response(),
+        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+    }
+}
+
+async fn get_health() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn get_readiness(State(state): State<AppState>) -> impl IntoResponse {
+    let (ready, details) = state.health.readiness();
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(details))
+}
+
+async fn get_approvals(State(state): State<AppState>) -> impl IntoResponse {
+    Json(state.approvals.pending())
+}
+
+async fn approve(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    decide(&state, &id, Decision::Approve)
+}
+
+async fn reject(State(state): State<AppState>, Path(id): Path<String>) -> impl IntoResponse {
+    decide(&state, &id, Decision::Reject)
+}
+
+/**
+ * `202` once the decision is handed to the bot, `404` if the order isn't
+ * parked, `503` if the bot isn't there to take it.
+ */
+fn decide(state: &AppState, order_id: &str, decision: Decision) -> impl IntoResponse {
+    match state.approvals.decide(order_id, decision) {
+        Ok(()) => {
+            info!("Order {}: {:?} by the operator", order_id, decision);
+            (
+                StatusCode::ACCEPTED,
+                Json(json!({ "order_id": order_id, "decision": decision })),
+            )
+        }
+        Err(e) => error_response(e, StatusCode::NOT_FOUND),
+    }
+}
+
+/// `503` when the bot is unavailable, `status` for other errors.
+fn error_response(
+    e: Box<dyn std::error::Error>,
+    status: StatusCode,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let status = if e.is::<BotUnavailableError>() {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        status
+    };
+    (status, Json(json!({ "error": e.to_string() })))
+}
+
+/// Lets requests through when they carry the token.
+async fn authorize(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(token) = &state.api_token else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+
+    let authorized = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|value| bool::from(value.as_bytes().ct_eq(token.as_bytes())));
+    if !authorized {
+        return (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Missing or wrong bearer token" })),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Answer of the service, `503` if the bot is unavailable, or `500` with its error.
+async fn ask(state: &AppState, request: control::Request) -> impl IntoResponse {
+    match state.control.ask(request).await {
+        Ok(answer) => (StatusCode::OK, Json(answer)),
+        Err(e) => error_response(e, StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+async fn get_orders(State(state): State<AppState>) -> impl IntoResponse {
+    ask(&state, control::Request::Orders).await
+}
+
+async fn reprocess_order(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> impl IntoResponse {
+    ask(&state, control::Request::Reprocess { order_id }).await
+}
+
+async fn accept_order(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> impl IntoResponse {
+    ask(&state, control::Request::Accept { order_id }).await
+}
+
+async fn reject_order(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> impl IntoResponse {
+    ask(&state, control::Request::Reject { order_id }).await
+}
+
+#[derive(Debug, Deserialize)]
+struct CancelQuery {
+    /// Reason given to Magma. Default is UNABLE_TO_PAY
+    reason: Option<OrderCancellationReason>,
+}
+
+async fn cancel_order(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+    Query(query): Query<CancelQuery>,
+) -> impl IntoResponse {
+    let reason = query
+        .reason
+        .unwrap_or(OrderCancellationReason::UNABLE_TO_PAY);
+    ask(&state, control::Request::Cancel { order_id, reason }).await
+}
+
+async fn open_channel(
+    State(state): State<AppState>,
+    Path(order_id): Path<String>,
+) -> impl IntoResponse {
+    ask(&state, control::Request::OpenChannel { order_id }).await
+}
+
+async fn get_liquidity(State(state): State<AppState>) -> impl IntoResponse {
+    ask(&state, control::Request::Liquidity).await
+}
+
+#[derive(Debug, Deserialize)]
+struct FeesQuery {
+    /// Channel size to fund, in sats
+    size: Option<i64>,
+}
+
+async fn get_fees(
+    State(state): State<AppState>,
+    Query(query): Query<FeesQuery>,
+) -> impl IntoResponse {
+    ask(&state, control::Request::Fees { size: query.size }).await
+}
+
+async fn get_status(State(state): State<AppState>) -> impl IntoResponse {
+    Json(json!({ "paused": state.control.is_paused() }))
+}
+
+async fn pause(State(state): State<AppState>) -> impl IntoResponse {
+    ask(&state, control::Request::SetPaused { paused: true }).await
+}
+
+async fn resume(State(state): State<AppState>) -> impl IntoResponse {
+    ask(&state, control::Request::SetPaused { paused: false }).await
+}
@@ -0,0 +1,130 @@
+use crate::errors::LeaseActiveError;
+use crate::store::{ChannelStage, SoldChannel, Store};
+
+/// Whether the buyer is still entitled to keep the channel open at `height`.
+///
+/// Channels not confirmed yet have no known lease end and count as leased.
+pub fn is_under_lease(channel: &SoldChannel, height: u32) -> bool {
+    if channel.stage == ChannelStage::Closed {
+        return false;
+    }
+
+    channel
+        .lease_end_height
+        .is_none_or(|lease_end_height| height < lease_end_height)
+}
+
+/// Sold channels that can't be closed yet, soonest to expire first.
+pub fn channels_under_lease(store: &Store, height: u32) -> Vec<&SoldChannel> {
+    let mut channels: Vec<&SoldChannel> = store
+        .channels
+        .values()
+        .filter(|channel| is_under_lease(channel, height))
+        .collect();
+
+    channels.sort_by_key(|channel| channel.lease_end_height.unwrap_or(u32::MAX));
+    channels
+}
+
+/// Fails if `channel_point` belongs to a sold channel still under lease.
+pub fn check_can_close(
+    store: &Store,
+    channel_point: &str,
+    height: u32,
+) -> Result<(), LeaseActiveError> {
+    let leased = store
+        .channels
+        .values()
+        .find(|channel| channel.channel_point == channel_point)
+        .filter(|channel| is_under_lease(channel, height));
+
+    match leased {
+        Some(channel) => Err(LeaseActiveError {
+            order_id: channel.order_id.clone(),
+            lease_end_height: channel.lease_end_height,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(order_id: &str, stage: ChannelStage, lease_end_height: Option<u32>) -> SoldChannel {
+        SoldChannel {
+            order_id: order_id.to_string(),
+            buyer: "buyer".to_string(),
+            size: 1_000_000,
+            channel_point: format!("{}:0", order_id),
+            chan_id: None,
+            stage,
+            magma_status: None,
+            min_block_length: 4320,
+            confirmation_height: None,
+            lease_elapsed_height: None,
+            lease_end_height,
+            fee_rate_cap: None,
+            base_fee_cap: None,
+            broadcast_height: None,
+            bumped_fee_rate: None,
+        }
+    }
+
+    #[test]
+    fn lease_ends_at_the_boundary_block() {
+        let channel = channel("a", ChannelStage::Open, Some(800_000));
+
+        assert!(is_under_lease(&channel, 799_999));
+        assert!(!is_under_lease(&channel, 800_000));
+        assert!(!is_under_lease(&channel, 800_001));
+    }
+
+    #[test]
+    fn unconfirmed_channels_are_leased() {
+        let channel = channel("a", ChannelStage::Pending, None);
+
+        assert!(is_under_lease(&channel, u32::MAX));
+    }
+
+    #[test]
+    fn closed_channels_are_not_leased() {
+        let channel = channel("a", ChannelStage::Closed, Some(800_000));
+
+        assert!(!is_under_lease(&channel, 0));
+    }
+
+    #[test]
+    fn channels_under_lease_sorts_soonest_first() {
+        let mut store = Store::default();
+        for channel in [
+            channel("pending", ChannelStage::Pending, None),
+            channel("late", ChannelStage::Open, Some(900_000)),
+            channel("over", ChannelStage::Open, Some(800_000)),
+            channel("soon", ChannelStage::Open, Some(850_000)),
+        ] {
+            store.channels.insert(channel.order_id.clone(), channel);
+        }
+
+        let order_ids: Vec<&str> = channels_under_lease(&store, 800_000)
+            .iter()
+            .map(|channel| channel.order_id.as_str())
+            .collect();
+        assert_eq!(order_ids, ["soon", "late", "pending"]);
+    }
+
+    #[test]
+    fn check_can_close_at_the_boundary_block() {
+        let mut store = Store::default();
+        store.channels.insert(
+            "a".to_string(),
+            channel("a", ChannelStage::Open, Some(800_000)),
+        );
+
+        let err = check_can_close(&store, "a:0", 799_999).unwrap_err();
+        assert_eq!(err.order_id, "a");
+        assert_eq!(err.lease_end_height, Some(800_000));
+        assert!(check_can_close(&store, "a:0", 800_000).is_ok());
+        assert!(check_can_close(&store, "unknown:0", 0).is_ok());
+    }
+}
@@ -1,25 +1,79 @@
 #![allow(unused)]
-use std::fs;
+use std::{env, fs, process};
 
 use api::Api;
+use clap::Parser;
+use cli::{Cli, Command, ConfigCommand, OrdersCommand};
 use config::load as load_config;
 use errors::ForbiddenError;
 use log::debug;
 use node::LNNode;
 use service::Service;
+use tracing_subscriber::EnvFilter;
 
 mod api;
+mod approval;
+mod bumping;
+mod chat;
+mod cli;
+mod clock;
+mod commands;
 mod config;
+mod control;
 mod errors;
+mod health;
+mod http;
+mod lease;
 mod mempool;
+mod metrics;
 mod node;
+mod notify;
+mod pricing;
+mod profitability;
 mod service;
+mod store;
 mod traits;
 
 #[tokio::main]
 async fn main() {
-    env_logger::init();
+    init_logging();
 
-    let mut service = Service::new().await;
-    service.start().await;
+    let cli = Cli::parse();
+
+    let result = match cli.command.unwrap_or(Command::Run) {
+        Command::Run => match Service::new().await {
+            Ok(mut service) => service.start().await,
+            Err(e) => Err(e),
+        },
+        Command::Orders {
+            command: OrdersCommand::List { status },
+        } => commands::orders_list(status.as_deref()).await,
+        Command::Order { command } => commands::order(command).await,
+        Command::Approvals { command } => commands::approvals(command).await,
+        Command::Login => commands::login().await,
+        Command::Config {
+            command: ConfigCommand::Check,
+        } => commands::config_check().await,
+        Command::Fees { size } => commands::fees(size).await,
+        Command::Leases => commands::leases().await,
+        Command::CloseChannel { channel_point } => commands::close_channel(&channel_point).await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Plain text logs by default, JSON lines with `LOG_FORMAT=json`. Both follow `RUST_LOG`.
+fn init_logging() {
+    if env::var("LOG_FORMAT").is_ok_and(|format| format == "json") {
+        // Also picks up the `log` records, with the spans they happen in
+        tracing_subscriber::fmt()
+            .json()
+            .with_env_filter(EnvFilter::from_default_env())
+            .init();
+    } else {
+        env_logger::init();
+    }
 }
@@ -0,0 +1,119 @@
+use prometheus::{
+    Counter, Encoder, Gauge, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts,
+    Registry, TextEncoder,
+};
+
+/// What the bot exports on `/metrics`. Cloning shares the same values.
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    /// Orders entering each status
+    pub orders_seen: IntCounterVec,
+    pub orders_accepted: IntCounterVec,
+    pub orders_rejected: IntCounterVec,
+    pub orders_cancelled: IntCounterVec,
+    pub channels_opened: IntCounter,
+    /// Size of the channels opened
+    pub sats_committed: IntCounter,
+    /// Estimated fees of the funding transactions
+    pub fees_paid: Counter,
+    /// Expected profit of the last channel opened, which may be negative
+    pub expected_profit: Gauge,
+    pub loop_duration: Histogram,
+    pub query_cost_remaining: Gauge,
+    /// Unix time of the last loop that fetched orders
+    pub last_successful_poll: IntGauge,
+    /// Unix time at which the Magma API key expires
+    pub api_key_expiry: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("magma_bot".to_string()), None)?;
+
+        let metrics = Metrics {
+            orders_seen: IntCounterVec::new(
+                Opts::new("orders_seen_total", "Orders seen entering a status"),
+                &["status"],
+            )?,
+            orders_accepted: IntCounterVec::new(
+                Opts::new("orders_accepted_total", "Orders accepted by the bot"),
+                &["reason"],
+            )?,
+            orders_rejected: IntCounterVec::new(
+                Opts::new("orders_rejected_total", "Orders rejected by the bot"),
+                &["reason"],
+            )?,
+            orders_cancelled: IntCounterVec::new(
+                Opts::new("orders_cancelled_total", "Orders cancelled by the bot"),
+                &["reason"],
+            )?,
+            channels_opened: IntCounter::new("channels_opened_total", "Channels opened")?,
+            sats_committed: IntCounter::new(
+                "sats_committed_total",
+                "Sats committed to the channels opened",
+            )?,
+            fees_paid: Counter::new(
+                "onchain_fees_sats_total",
+                "Estimated on-chain fees paid to open channels, in sats",
+            )?,
+            expected_profit: Gauge::new(
+                "expected_profit_sats",
+                "Expected profit of the last channel opened, in sats",
+            )?,
+            loop_duration: Histogram::with_opts(HistogramOpts::new(
+                "loop_duration_seconds",
+                "Duration of the order processing loop",
+            ))?,
+            query_cost_remaining: Gauge::new(
+                "query_cost_remaining",
+                "Magma API query cost available",
+            )?,
+            last_successful_poll: IntGauge::new(
+                "last_successful_poll_timestamp_seconds",
+                "Unix time of the last successful order poll",
+            )?,
+            api_key_expiry: IntGauge::new(
+                "api_key_expiry_timestamp_seconds",
+                "Unix time at which the Magma API key expires",
+            )?,
+            registry,
+        };
+
+        metrics.register()?;
+        Ok(metrics)
+    }
+
+    fn register(&self) -> Result<(), prometheus::Error> {
+        self.registry.register(Box::new(self.orders_seen.clone()))?;
+        self.registry
+            .register(Box::new(self.orders_accepted.clone()))?;
+        self.registry
+            .register(Box::new(self.orders_rejected.clone()))?;
+        self.registry
+            .register(Box::new(self.orders_cancelled.clone()))?;
+        self.registry
+            .register(Box::new(self.channels_opened.clone()))?;
+        self.registry
+            .register(Box::new(self.sats_committed.clone()))?;
+        self.registry.register(Box::new(self.fees_paid.clone()))?;
+        self.registry
+            .register(Box::new(self.expected_profit.clone()))?;
+        self.registry
+            .register(Box::new(self.loop_duration.clone()))?;
+        self.registry
+            .register(Box::new(self.query_cost_remaining.clone()))?;
+        self.registry
+            .register(Box::new(self.last_successful_poll.clone()))?;
+        self.registry
+            .register(Box::new(self.api_key_expiry.clone()))?;
+        Ok(())
+    }
+
+    /// All metrics in the Prometheus text format.
+    pub fn render(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
@@ -1,13 +1,21 @@
 use crate::traits::Signer;
 use lnd_grpc_rust::lnrpc;
+use lnd_grpc_rust::lnrpc::channel_point::FundingTxid;
+use lnd_grpc_rust::lnrpc::close_status_update;
+use lnd_grpc_rust::lnrpc::policy_update_request;
 use lnd_grpc_rust::walletrpc;
 use lnd_grpc_rust::LndClient;
-use log::debug;
+use log::{debug, warn};
 use serde::de;
 use serde::Deserialize;
 use std::{cell::RefCell, fs, path::PathBuf};
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration};
 use tonic::client;
 
+/// Lease id of the UTXOs the bot reserves for accepted orders
+const UTXO_LOCK_ID: &[u8; 32] = b"amboss-magma-bot/reserved-utxos!";
+
 #[derive(Debug, Deserialize)]
 pub struct LNDConfig {
     pub host: String,
@@ -125,7 +133,8 @@ impl LNNode {
         &self,
         amount: i64,
         expiry: i64,
-    ) -> Result<String, Box<dyn std::error::Error>> {
+        memo: &str,
+    ) -> Result<lnrpc::AddInvoiceResponse, Box<dyn std::error::Error>> {
         let mut client = self.client.borrow_mut();
 
         let invoice = client
@@ -133,15 +142,150 @@ impl LNNode {
             .add_invoice(lnrpc::Invoice {
                 value: amount,
                 expiry: expiry,
+                memo: memo.to_string(),
                 ..Default::default()
             })
             .await?
-            .into_inner()
-            .payment_request;
+            .into_inner();
 
         Ok(invoice)
     }
 
+    pub async fn lookup_invoice(
+        &self,
+        r_hash: Vec<u8>,
+    ) -> Result<lnrpc::Invoice, Box<dyn std::error::Error>> {
+        let mut lightning = self.client.borrow_mut().lightning().clone();
+
+        let invoice = lightning
+            .lookup_invoice(lnrpc::PaymentHash {
+                r_hash,
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+
+        Ok(invoice)
+    }
+
+    /// Streams every invoice settled on the node after `settle_index`, or from
+    /// now on when it's 0.
+    ///
+    /// The subscription runs on its own task and is re-established after
+    /// errors, resuming from the last settle index seen. The task stops once
+    /// the returned receiver is dropped.
+    pub fn subscribe_invoices(
+        &self,
+        mut settle_index: u64,
+    ) -> mpsc::UnboundedReceiver<lnrpc::Invoice> {
+        let mut lightning = self.client.borrow_mut().lightning().clone();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let request = lnrpc::InvoiceSubscription {
+                    add_index: 0,
+                    settle_index,
+                };
+
+                let mut stream = match lightning.subscribe_invoices(request).await {
+                    Ok(response) => response.into_inner(),
+                    Err(e) => {
+                        warn!("Error subscribing to invoices: {}", e);
+                        sleep(Duration::from_secs(10)).await;
+                        continue;
+                    }
+                };
+                debug!("Subscribed to invoices");
+
+                loop {
+                    match stream.message().await {
+                        Ok(Some(invoice)) => {
+                            if invoice.state() != lnrpc::invoice::InvoiceState::Settled {
+                                continue;
+                            }
+                            settle_index = settle_index.max(invoice.settle_index);
+
+                            if tx.send(invoice).is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => {
+                            warn!("Invoice subscription closed by LND");
+                            break;
+                        }
+                        Err(e) => {
+                            warn!("Invoice subscription error: {}", e);
+                            break;
+                        }
+                    }
+                }
+
+                sleep(Duration::from_secs(10)).await;
+            }
+        });
+
+        rx
+    }
+
+    /// Streams channel updates (pending, open, active, closed...) from now on.
+    ///
+    /// Like the invoice subscription, it runs on its own task, reconnects on
+    /// errors and stops once the returned receiver is dropped.
+    pub fn subscribe_channel_events(&self) -> mpsc::UnboundedReceiver<lnrpc::ChannelEventUpdate> {
+        let mut lightning = self.client.borrow_mut().lightning().clone();
+        let (tx, rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            loop {
+                let request = lnrpc::ChannelEventSubscription {};
+
+                let mut stream = match lightning.subscribe_channel_events(request).await {
+                    Ok(response) => response.into_inner(),
+                    Err(e) => {
+                        warn!("Error subscribing to channel events: {}", e);
+                        sleep(Duration::from_secs(10)).await;
+                        continue;
+                    }
+                };
+                debug!("Subscribed to channel events");
+
+                loop {
+                    match stream.message().await {
+                        Ok(Some(event)) => {
+                            if tx.send(event).is_err() {
+                                return;
+                            }
+                        }
+                        Ok(None) => {
+                            warn!("Channel event subscription closed by LND");
+                            break;
+                        }
+                        Err(e) => {
+                            warn!("Channel event subscription error: {}", e);
+                            break;
+                        }
+                    }
+                }
+
+                sleep(Duration::from_secs(10)).await;
+            }
+        });
+
+        rx
+    }
+
+    pub async fn get_info(&self) -> Result<lnrpc::GetInfoResponse, Box<dyn std::error::Error>> {
+        let mut lightning = self.client.borrow_mut().lightning().clone();
+
+        let info = lightning
+            .get_info(lnrpc::GetInfoRequest {})
+            .await?
+            .into_inner();
+
+        Ok(info)
+    }
+
     pub async fn open_channel(
         &self,
         node_pubkey: Vec<u8>,
@@ -167,6 +311,175 @@ impl LNNode {
         Ok(channel)
     }
 
+    pub async fn list_channels(&self) -> Result<Vec<lnrpc::Channel>, Box<dyn std::error::Error>> {
+        let mut lightning = self.client.borrow_mut().lightning().clone();
+
+        let channels = lightning
+            .list_channels(lnrpc::ListChannelsRequest::default())
+            .await?
+            .into_inner()
+            .channels;
+
+        Ok(channels)
+    }
+
+    /// Cooperatively closes a channel, returning the closing txid once it is
+    /// broadcast.
+    pub async fn close_channel(
+        &self,
+        channel_point: &str,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut lightning = self.client.borrow_mut().lightning().clone();
+
+        debug!("Closing channel: {}", channel_point);
+
+        let mut updates = lightning
+            .close_channel(lnrpc::CloseChannelRequest {
+                channel_point: Some(parse_channel_point(channel_point)?),
+                force: false,
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+
+        while let Some(update) = updates.message().await? {
+            if let Some(close_status_update::Update::ClosePending(pending)) = update.update {
+                // LND returns the txid bytes in internal (reversed) order
+                let closing_txid = pending.txid.iter().rev().copied().collect::<Vec<u8>>();
+                return Ok(hex::encode(closing_txid));
+            }
+        }
+
+        Err("Channel close stream ended before the closing transaction was broadcast".into())
+    }
+
+    pub async fn get_chan_info(
+        &self,
+        chan_id: u64,
+    ) -> Result<lnrpc::ChannelEdge, Box<dyn std::error::Error>> {
+        let mut lightning = self.client.borrow_mut().lightning().clone();
+
+        let edge = lightning
+            .get_chan_info(lnrpc::ChanInfoRequest {
+                chan_id,
+                ..Default::default()
+            })
+            .await?
+            .into_inner();
+
+        Ok(edge)
+    }
+
+    pub async fn update_channel_policy(
+        &self,
+        channel_point: &str,
+        base_fee_msat: i64,
+        fee_rate_ppm: u32,
+        time_lock_delta: u32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut lightning = self.client.borrow_mut().lightning().clone();
+
+        let failed_updates = lightning
+            .update_channel_policy(lnrpc::PolicyUpdateRequest {
+                scope: Some(policy_update_request::Scope::ChanPoint(
+                    parse_channel_point(channel_point)?,
+                )),
+                base_fee_msat,
+                fee_rate_ppm,
+                time_lock_delta,
+                ..Default::default()
+            })
+            .await?
+            .into_inner()
+            .failed_updates;
+
+        if let Some(failed) = failed_updates.first() {
+            return Err(format!("Policy update failed: {}", failed.update_error).into());
+        }
+
+        Ok(())
+    }
+
+    /// Locks an UTXO so LND doesn't spend it elsewhere, for `expiration_seconds`.
+    pub async fn lease_output(
+        &self,
+        outpoint: &str,
+        expiration_seconds: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut wallet = self.client.borrow_mut().wallet().clone();
+
+        wallet
+            .lease_output(walletrpc::LeaseOutputRequest {
+                id: UTXO_LOCK_ID.to_vec(),
+                outpoint: Some(parse_outpoint(outpoint)?),
+                expiration_seconds,
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn release_output(&self, outpoint: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut wallet = self.client.borrow_mut().wallet().clone();
+
+        wallet
+            .release_output(walletrpc::ReleaseOutputRequest {
+                id: UTXO_LOCK_ID.to_vec(),
+                outpoint: Some(parse_outpoint(outpoint)?),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Wallet transactions not confirmed yet.
+    pub async fn list_unconfirmed_transactions(
+        &self,
+    ) -> Result<Vec<lnrpc::Transaction>, Box<dyn std::error::Error>> {
+        let mut lightning = self.client.borrow_mut().lightning().clone();
+        let height = self.get_info().await?.block_height;
+
+        let transactions = lightning
+            .get_transactions(lnrpc::GetTransactionsRequest {
+                start_height: height as i32,
+                end_height: -1,
+                ..Default::default()
+            })
+            .await?
+            .into_inner()
+            .transactions
+            .into_iter()
+            .filter(|tx| tx.num_confirmations == 0)
+            .collect();
+
+        Ok(transactions)
+    }
+
+    /// Asks the sweeper to spend one of our outputs at a higher fee rate,
+    /// pulling its unconfirmed parent along (CPFP).
+    pub async fn bump_fee(
+        &self,
+        outpoint: &str,
+        sat_per_vbyte: u64,
+        budget: u64,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let mut wallet = self.client.borrow_mut().wallet().clone();
+
+        let status = wallet
+            .bump_fee(walletrpc::BumpFeeRequest {
+                outpoint: Some(parse_outpoint(outpoint)?),
+                sat_per_vbyte,
+                immediate: true,
+                budget,
+                ..Default::default()
+            })
+            .await?
+            .into_inner()
+            .status;
+
+        Ok(status)
+    }
+
     pub async fn list_unspent(&self) -> Result<Vec<lnrpc::Utxo>, Box<dyn std::error::Error>> {
         let mut client = self.client.borrow_mut();
 
@@ -192,6 +505,50 @@ impl Signer for LNNode {
     }
 }
 
+/// Formats a channel point as `txid:output_index`.
+pub fn format_channel_point(channel_point: &lnrpc::ChannelPoint) -> String {
+    let txid = match &channel_point.funding_txid {
+        // LND returns the txid bytes in internal (reversed) order
+        Some(FundingTxid::FundingTxidBytes(bytes)) => {
+            hex::encode(bytes.iter().rev().copied().collect::<Vec<u8>>())
+        }
+        Some(FundingTxid::FundingTxidStr(txid)) => txid.clone(),
+        None => String::new(),
+    };
+
+    format!("{}:{}", txid, channel_point.output_index)
+}
+
+fn parse_channel_point(
+    channel_point: &str,
+) -> Result<lnrpc::ChannelPoint, Box<dyn std::error::Error>> {
+    let (txid, output_index) = channel_point
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid channel point: {}", channel_point))?;
+
+    Ok(lnrpc::ChannelPoint {
+        funding_txid: Some(FundingTxid::FundingTxidStr(txid.to_string())),
+        output_index: output_index.parse()?,
+    })
+}
+
+/// Formats an outpoint as `txid:output_index`.
+pub fn format_outpoint(outpoint: &lnrpc::OutPoint) -> String {
+    format!("{}:{}", outpoint.txid_str, outpoint.output_index)
+}
+
+fn parse_outpoint(outpoint: &str) -> Result<lnrpc::OutPoint, Box<dyn std::error::Error>> {
+    let (txid, output_index) = outpoint
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid outpoint: {}", outpoint))?;
+
+    Ok(lnrpc::OutPoint {
+        txid_str: txid.to_string(),
+        output_index: output_index.parse()?,
+        ..Default::default()
+    })
+}
+
 fn expand_tilde(path: &str) -> PathBuf {
     if path.starts_with("~/") {
         if let Some(home) = dirs::home_dir() {
@@ -0,0 +1,142 @@
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
+
+mod email;
+mod nostr;
+mod telegram;
+mod webhook;
+
+pub use email::EmailConfig;
+pub use nostr::NostrConfig;
+pub use telegram::TelegramConfig;
+pub use webhook::WebhookConfig;
+
+/// Kinds of events the bot notifies about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    OrderAccepted,
+    OrderRejected,
+    OrderCancelled,
+    /// The wallet can't fund an order
+    InsufficientFunds,
+    ChannelOpened,
+    /// Magma marked an order as failed on our side
+    OrderFailed,
+    ApiKeyRotated,
+    /// The order loop keeps failing
+    LoopFailing,
+    /// The order loop works again after failing
+    LoopRecovered,
+    /// Someone wrote in the chat of an order
+    OrderMessage,
+    /// An order waits for the operator's approval
+    ApprovalNeeded,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    pub kind: EventKind,
+    pub order_id: Option<String>,
+    pub message: String,
+}
+
+impl Event {
+    pub fn new(kind: EventKind, order_id: Option<&str>, message: impl Into<String>) -> Self {
+        Event {
+            kind,
+            order_id: order_id.map(str::to_string),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.order_id {
+            Some(order_id) => write!(f, "Magma order {}: {}", order_id, self.message),
+            None => write!(f, "Magma bot: {}", self.message),
+        }
+    }
+}
+
+/// Somewhere to send events to.
+#[async_trait::async_trait]
+pub trait Notifier: Send + Sync {
+    async fn notify(&self, event: &Event) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SinkConfig {
+    Telegram(TelegramConfig),
+    Email(EmailConfig),
+    Webhook(WebhookConfig),
+    Nostr(NostrConfig),
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NotificationConfig {
+    #[serde(flatten)]
+    pub sink: SinkConfig,
+    /// Events to send. Default is all of them
+    pub events: Option<Vec<EventKind>>,
+}
+
+struct Sink {
+    notifier: Arc<dyn Notifier>,
+    events: Option<Vec<EventKind>>,
+}
+
+/// Sends events to every configured sink that wants them.
+#[derive(Default)]
+pub struct Notifications {
+    sinks: Vec<Sink>,
+}
+
+impl Notifications {
+    pub fn new(configs: Vec<NotificationConfig>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut sinks = Vec::new();
+
+        for config in configs {
+            let notifier: Arc<dyn Notifier> = match config.sink {
+                SinkConfig::Telegram(config) => Arc::new(telegram::Telegram::new(config)),
+                SinkConfig::Email(config) => Arc::new(email::Email::new(config)?),
+                SinkConfig::Webhook(config) => Arc::new(webhook::Webhook::new(config)),
+                SinkConfig::Nostr(config) => Arc::new(nostr::Nostr::new(config)?),
+            };
+            sinks.push(Sink {
+                notifier,
+                events: config.events,
+            });
+        }
+
+        Ok(Notifications { sinks })
+    }
+
+    /// Sends the event in the background, so a slow sink never holds the bot.
+    pub fn notify(&self, event: Event) {
+        debug!("Notifying: {}", event);
+
+        let event = Arc::new(event);
+        for sink in &self.sinks {
+            let wanted = sink
+                .events
+                .as_ref()
+                .is_none_or(|events| events.contains(&event.kind));
+            if !wanted {
+                continue;
+            }
+
+            let notifier = sink.notifier.clone();
+            let event = event.clone();
+            tokio::spawn(async move {
+                if let Err(e) = notifier.notify(&event).await {
+                    warn!("Error sending notification: {}", e);
+                }
+            });
+        }
+    }
+}
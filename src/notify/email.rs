@@ -0,0 +1,54 @@
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::Deserialize;
+
+use super::{Event, Notifier};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct EmailConfig {
+    /// SMTP server, reached over TLS
+    pub smtp_host: String,
+    /// Default is 465
+    pub smtp_port: Option<u16>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub from: String,
+    pub to: String,
+}
+
+pub struct Email {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+    to: Mailbox,
+}
+
+impl Email {
+    pub fn new(config: EmailConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut transport = AsyncSmtpTransport::<Tokio1Executor>::relay(&config.smtp_host)?
+            .port(config.smtp_port.unwrap_or(465));
+        if let (Some(username), Some(password)) = (config.username, config.password) {
+            transport = transport.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Email {
+            transport: transport.build(),
+            from: config.from.parse()?,
+            to: config.to.parse()?,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for Email {
+    async fn notify(&self, event: &Event) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(self.to.clone())
+            .subject(event.to_string())
+            .body(event.message.clone())?;
+
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,529 @@
+use aes::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20::cipher::StreamCipher;
+use chacha20::ChaCha20;
+use futures_util::future::join_all;
+use futures_util::{SinkExt, StreamExt};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use log::debug;
+use secp256k1::rand::{thread_rng, Rng, RngCore};
+use secp256k1::{ecdh, Keypair, Message, Parity, PublicKey, SecretKey, XOnlyPublicKey, SECP256K1};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio::time::{timeout, Duration};
+use tokio_tungstenite::{connect_async, tungstenite};
+
+use crate::clock::now;
+
+use super::{Event, Notifier};
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long a relay gets to accept an event.
+const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NostrProtocol {
+    /// Encrypted direct message (kind 4), widely supported but leaks metadata
+    Nip04,
+    /// Gift wrapped private direct message (kind 1059)
+    Nip17,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NostrConfig {
+    /// Dedicated key of the bot, as `nsec` or hex
+    pub secret_key: String,
+    /// Who gets the messages, as `npub` or hex
+    pub recipients: Vec<String>,
+    /// Relays to publish to, e.g. `wss://relay.damus.io`
+    pub relays: Vec<String>,
+    /// Default is nip17
+    pub protocol: Option<NostrProtocol>,
+}
+
+pub struct Nostr {
+    keys: Keypair,
+    recipients: Vec<XOnlyPublicKey>,
+    relays: Vec<String>,
+    protocol: NostrProtocol,
+}
+
+/// A Nostr event, unsigned for NIP-17 rumors.
+#[derive(Debug, Serialize)]
+struct NostrEvent {
+    id: String,
+    pubkey: String,
+    created_at: u64,
+    kind: u16,
+    tags: Vec<Vec<String>>,
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sig: Option<String>,
+}
+
+impl Nostr {
+    pub fn new(config: NostrConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let secret_key = SecretKey::from_slice(&decode_key(&config.secret_key, "nsec")?)?;
+        let recipients = config
+            .recipients
+            .iter()
+            .map(|recipient| Ok(XOnlyPublicKey::from_slice(&decode_key(recipient, "npub")?)?))
+            .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+
+        Ok(Nostr {
+            keys: Keypair::from_secret_key(SECP256K1, &secret_key),
+            recipients,
+            relays: config.relays,
+            protocol: config.protocol.unwrap_or(NostrProtocol::Nip17),
+        })
+    }
+
+    fn direct_message(&self, recipient: &XOnlyPublicKey, text: &str) -> Result<NostrEvent, Error> {
+        let p_tag = vec![vec!["p".to_string(), hex::encode(recipient.serialize())]];
+
+        match self.protocol {
+            NostrProtocol::Nip04 => {
+                let content = nip04_encrypt(&self.keys.secret_key(), recipient, text)?;
+                Ok(sign(&self.keys, now(), 4, p_tag, content))
+            }
+            NostrProtocol::Nip17 => {
+                // The message itself, never published as is
+                let rumor = unsigned(&self.keys, now(), 14, p_tag.clone(), text.to_string());
+
+                // Sealed by us, so the recipient knows who sent it
+                let seal = sign(
+                    &self.keys,
+                    random_past(),
+                    13,
+                    Vec::new(),
+                    nip44_encrypt(
+                        &self.keys.secret_key(),
+                        recipient,
+                        &json!(rumor).to_string(),
+                    )?,
+                );
+
+                // Wrapped with a throwaway key, hiding the sender from relays
+                let ephemeral = Keypair::new(SECP256K1, &mut thread_rng());
+                Ok(sign(
+                    &ephemeral,
+                    random_past(),
+                    1059,
+                    p_tag,
+                    nip44_encrypt(&ephemeral.secret_key(), recipient, &json!(seal).to_string())?,
+                ))
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for Nostr {
+    async fn notify(&self, event: &Event) -> Result<(), Error> {
+        let text = event.to_string();
+
+        for recipient in &self.recipients {
+            let message = self.direct_message(recipient, &text)?;
+            let results = join_all(self.relays.iter().map(|relay| publish(relay, &message))).await;
+
+            let mut errors = Vec::new();
+            for (relay, result) in self.relays.iter().zip(results) {
+                match result {
+                    Ok(()) => debug!("Nostr message published to {}", relay),
+                    Err(e) => errors.push(format!("{}: {}", relay, e)),
+                }
+            }
+            if errors.len() == self.relays.len() {
+                Err(format!(
+                    "No relay accepted the Nostr message: {}",
+                    errors.join(", ")
+                ))?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sends an event to a relay and waits for it to be accepted.
+async fn publish(relay: &str, event: &NostrEvent) -> Result<(), Error> {
+    timeout(RELAY_TIMEOUT, async {
+        let (mut socket, _) = connect_async(relay).await?;
+        socket
+            .send(tungstenite::Message::text(
+                json!(["EVENT", event]).to_string(),
+            ))
+            .await?;
+
+        // Relays answer ["OK", <event id>, <accepted>, <message>]
+        while let Some(message) = socket.next().await {
+            let tungstenite::Message::Text(text) = message? else {
+                continue;
+            };
+            let Ok(Value::Array(answer)) = serde_json::from_str(&text) else {
+                continue;
+            };
+            if answer.first().and_then(Value::as_str) != Some("OK")
+                || answer.get(1).and_then(Value::as_str) != Some(event.id.as_str())
+            {
+                continue;
+            }
+
+            let _ = socket.close(None).await;
+            return match answer.get(2).and_then(Value::as_bool) {
+                Some(true) => Ok(()),
+                _ => Err(format!("rejected: {}", answer.get(3).unwrap_or(&Value::Null)).into()),
+            };
+        }
+
+        Err("connection closed".into())
+    })
+    .await
+    .map_err(|_| "timed out")?
+}
+
+fn unsigned(
+    keys: &Keypair,
+    created_at: u64,
+    kind: u16,
+    tags: Vec<Vec<String>>,
+    content: String,
+) -> NostrEvent {
+    let pubkey = hex::encode(keys.x_only_public_key().0.serialize());
+    let id = Sha256::digest(json!([0, pubkey, created_at, kind, tags, content]).to_string());
+
+    NostrEvent {
+        id: hex::encode(id),
+        pubkey,
+        created_at,
+        kind,
+        tags,
+        content,
+        sig: None,
+    }
+}
+
+fn sign(
+    keys: &Keypair,
+    created_at: u64,
+    kind: u16,
+    tags: Vec<Vec<String>>,
+    content: String,
+) -> NostrEvent {
+    let mut event = unsigned(keys, created_at, kind, tags, content);
+
+    let mut id = [0u8; 32];
+    hex::decode_to_slice(&event.id, &mut id).expect("event id is hex");
+    let sig = SECP256K1.sign_schnorr(&Message::from_digest(id), keys);
+    event.sig = Some(hex::encode(sig.serialize()));
+
+    event
+}
+
+/// x coordinate of the ECDH point, the shared secret of NIP-04 and NIP-44.
+fn shared_x(secret_key: &SecretKey, public_key: &XOnlyPublicKey) -> [u8; 32] {
+    let public_key = PublicKey::from_x_only_public_key(*public_key, Parity::Even);
+    let point = ecdh::shared_secret_point(&public_key, secret_key);
+
+    let mut x = [0u8; 32];
+    x.copy_from_slice(&point[..32]);
+    x
+}
+
+/// AES-256-CBC, as `<base64 ciphertext>?iv=<base64 iv>`.
+fn nip04_encrypt(
+    secret_key: &SecretKey,
+    public_key: &XOnlyPublicKey,
+    text: &str,
+) -> Result<String, Error> {
+    let key = shared_x(secret_key, public_key);
+    let mut iv = [0u8; 16];
+    thread_rng().fill_bytes(&mut iv);
+
+    let ciphertext = cbc::Encryptor::<aes::Aes256>::new(&key.into(), &iv.into())
+        .encrypt_padded_vec_mut::<Pkcs7>(text.as_bytes());
+
+    Ok(format!(
+        "{}?iv={}",
+        BASE64.encode(ciphertext),
+        BASE64.encode(iv)
+    ))
+}
+
+/// NIP-44 version 2: padded ChaCha20 with an HMAC-SHA256.
+fn nip44_encrypt(
+    secret_key: &SecretKey,
+    public_key: &XOnlyPublicKey,
+    text: &str,
+) -> Result<String, Error> {
+    let mut nonce = [0u8; 32];
+    thread_rng().fill_bytes(&mut nonce);
+
+    nip44_encrypt_with(
+        &nip44_conversation_key(secret_key, public_key),
+        &nonce,
+        text,
+    )
+}
+
+/// Key shared by both sides of a NIP-44 conversation.
+fn nip44_conversation_key(secret_key: &SecretKey, public_key: &XOnlyPublicKey) -> [u8; 32] {
+    let (conversation_key, _) =
+        Hkdf::<Sha256>::extract(Some(b"nip44-v2"), &shared_x(secret_key, public_key));
+    conversation_key.into()
+}
+
+fn nip44_encrypt_with(
+    conversation_key: &[u8; 32],
+    nonce: &[u8; 32],
+    text: &str,
+) -> Result<String, Error> {
+    let keys = nip44_message_keys(conversation_key, nonce)?;
+
+    let mut ciphertext = nip44_pad(text)?;
+    ChaCha20::new(&keys.chacha_key.into(), &keys.chacha_nonce.into())
+        .apply_keystream(&mut ciphertext);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&keys.hmac_key).map_err(|_| "invalid HMAC key")?;
+    mac.update(nonce);
+    mac.update(&ciphertext);
+
+    let mut payload = vec![2u8];
+    payload.extend_from_slice(nonce);
+    payload.extend_from_slice(&ciphertext);
+    payload.extend_from_slice(&mac.finalize().into_bytes());
+
+    Ok(BASE64.encode(payload))
+}
+
+/// Keys of a single NIP-44 message.
+struct MessageKeys {
+    chacha_key: [u8; 32],
+    chacha_nonce: [u8; 12],
+    hmac_key: [u8; 32],
+}
+
+fn nip44_message_keys(conversation_key: &[u8; 32], nonce: &[u8; 32]) -> Result<MessageKeys, Error> {
+    let mut keys = [0u8; 76];
+    Hkdf::<Sha256>::from_prk(conversation_key)
+        .map_err(|_| "invalid conversation key")?
+        .expand(nonce, &mut keys)
+        .map_err(|_| "invalid key length")?;
+
+    let mut message_keys = MessageKeys {
+        chacha_key: [0u8; 32],
+        chacha_nonce: [0u8; 12],
+        hmac_key: [0u8; 32],
+    };
+    message_keys.chacha_key.copy_from_slice(&keys[..32]);
+    message_keys.chacha_nonce.copy_from_slice(&keys[32..44]);
+    message_keys.hmac_key.copy_from_slice(&keys[44..]);
+    Ok(message_keys)
+}
+
+/// Length prefix, then the text padded with zeros to hide its exact length.
+fn nip44_pad(text: &str) -> Result<Vec<u8>, Error> {
+    let len = text.len();
+    if len == 0 || len > 65535 {
+        Err(format!("Can't encrypt a {} bytes message", len))?;
+    }
+
+    let padded_len = if len <= 32 {
+        32
+    } else {
+        let next_power = 1usize << (usize::BITS - (len - 1).leading_zeros());
+        let chunk = if next_power <= 256 {
+            32
+        } else {
+            next_power / 8
+        };
+        chunk * ((len - 1) / chunk + 1)
+    };
+
+    let mut padded = (len as u16).to_be_bytes().to_vec();
+    padded.extend_from_slice(text.as_bytes());
+    padded.resize(2 + padded_len, 0);
+    Ok(padded)
+}
+
+/// Key bytes from their bech32 (`nsec`, `npub`) or hex form.
+fn decode_key(key: &str, hrp: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if key.starts_with(hrp) {
+        let (_, bytes) = bech32::decode(key)?;
+        return Ok(bytes);
+    }
+    Ok(hex::decode(key)?)
+}
+
+/// NIP-59 tweaks the timestamps of seals and wraps up to two days back.
+fn random_past() -> u64 {
+    now() - thread_rng().gen_range(0..2 * 24 * 60 * 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::cipher::BlockDecryptMut;
+
+    use super::*;
+
+    // Vectors from https://github.com/paulmillr/nip44/blob/main/nip44.vectors.json
+
+    fn secret_key(hex: &str) -> SecretKey {
+        SecretKey::from_slice(&hex::decode(hex).unwrap()).unwrap()
+    }
+
+    fn public_key(secret_key: &SecretKey) -> XOnlyPublicKey {
+        secret_key.x_only_public_key(SECP256K1).0
+    }
+
+    fn bytes32(hex: &str) -> [u8; 32] {
+        hex::decode(hex).unwrap().try_into().unwrap()
+    }
+
+    fn nip44_decrypt(conversation_key: &[u8; 32], payload: &str) -> String {
+        let payload = BASE64.decode(payload).unwrap();
+        assert_eq!(payload[0], 2);
+        let nonce: [u8; 32] = payload[1..33].try_into().unwrap();
+        let (ciphertext, mac) = payload[33..].split_at(payload.len() - 33 - 32);
+
+        let keys = nip44_message_keys(conversation_key, &nonce).unwrap();
+        let mut expected = Hmac::<Sha256>::new_from_slice(&keys.hmac_key).unwrap();
+        expected.update(&nonce);
+        expected.update(ciphertext);
+        expected.verify_slice(mac).unwrap();
+
+        let mut padded = ciphertext.to_vec();
+        ChaCha20::new(&keys.chacha_key.into(), &keys.chacha_nonce.into())
+            .apply_keystream(&mut padded);
+        let len = u16::from_be_bytes([padded[0], padded[1]]) as usize;
+        String::from_utf8(padded[2..2 + len].to_vec()).unwrap()
+    }
+
+    fn nip04_decrypt(secret_key: &SecretKey, public_key: &XOnlyPublicKey, payload: &str) -> String {
+        let (ciphertext, iv) = payload.split_once("?iv=").unwrap();
+        let key = shared_x(secret_key, public_key);
+        let iv = BASE64.decode(iv).unwrap();
+
+        let text = cbc::Decryptor::<aes::Aes256>::new(&key.into(), iv.as_slice().into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&BASE64.decode(ciphertext).unwrap())
+            .unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn nip44_conversation_key_vector() {
+        let sec1 = secret_key("315e59ff51cb9209768cf7da80791ddcaae56ac9775eb25b6dee1234bc5d2268");
+        let pub2 = XOnlyPublicKey::from_slice(&bytes32(
+            "c2f9d9948dc8c7c38321e4b85c8558872eafa0641cd269db76848a6073e69133",
+        ))
+        .unwrap();
+
+        assert_eq!(
+            hex::encode(nip44_conversation_key(&sec1, &pub2)),
+            "3dfef0ce2a4d80a25e7a328accf73448ef67096f65f79588e358d9a0eb9013f1"
+        );
+    }
+
+    #[test]
+    fn nip44_conversation_key_is_shared() {
+        let sec1 = secret_key("0000000000000000000000000000000000000000000000000000000000000001");
+        let sec2 = secret_key("0000000000000000000000000000000000000000000000000000000000000002");
+
+        let key = nip44_conversation_key(&sec1, &public_key(&sec2));
+        assert_eq!(key, nip44_conversation_key(&sec2, &public_key(&sec1)));
+        assert_eq!(
+            hex::encode(key),
+            "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d"
+        );
+    }
+
+    #[test]
+    fn nip44_padding_vectors() {
+        for (len, padded) in [
+            (16, 32),
+            (32, 32),
+            (33, 64),
+            (37, 64),
+            (45, 64),
+            (49, 64),
+            (64, 64),
+            (65, 96),
+            (100, 128),
+            (111, 128),
+            (200, 224),
+            (250, 256),
+            (320, 320),
+            (383, 384),
+            (384, 384),
+            (400, 448),
+            (500, 512),
+            (512, 512),
+            (515, 640),
+            (700, 768),
+            (800, 896),
+            (900, 1024),
+            (1020, 1024),
+        ] {
+            let text = "a".repeat(len);
+            assert_eq!(nip44_pad(&text).unwrap().len(), 2 + padded, "{}", len);
+        }
+    }
+
+    #[test]
+    fn nip44_rejects_empty_and_long_messages() {
+        assert!(nip44_pad("").is_err());
+        assert!(nip44_pad(&"a".repeat(65535)).is_ok());
+        assert!(nip44_pad(&"a".repeat(65536)).is_err());
+    }
+
+    #[test]
+    fn nip44_encrypt_vectors() {
+        for (conversation_key, nonce, text, payload) in [
+            (
+                "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "a",
+                "AgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABee0G5VSK0/9YypIObAtDKfYEAjD35uVkHyB0F4DwrcNaCXlCWZKaArsGrY6M9wnuTMxWfp1RTN9Xga8no+kF5Vsb",
+            ),
+            (
+                "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+                "f00000000000000000000000000000f00000000000000000000000000000000f",
+                "🍕🫃",
+                "AvAAAAAAAAAAAAAAAAAAAPAAAAAAAAAAAAAAAAAAAAAPSKSK6is9ngkX2+cSq85Th16oRTISAOfhStnixqZziKMDvB0QQzgFZdjLTPicCJaV8nDITO+QfaQ61+KbWQIOO2Yj",
+            ),
+        ] {
+            let conversation_key = bytes32(conversation_key);
+            let encrypted =
+                nip44_encrypt_with(&conversation_key, &bytes32(nonce), text).unwrap();
+
+            assert_eq!(encrypted, payload);
+            assert_eq!(nip44_decrypt(&conversation_key, payload), text);
+        }
+    }
+
+    #[test]
+    fn nip44_round_trip() {
+        let sec1 = secret_key("5c0c523f52a5b6fad39ed2403092df8cebc36318b39383bca6c00808626fab3a");
+        let sec2 = secret_key("4b22aa260e4acb7021e32f38a6cdf4b673c6a277755bfce287e370c924dc936d");
+        let text = "A new order waits for approval";
+
+        let payload = nip44_encrypt(&sec1, &public_key(&sec2), text).unwrap();
+        let conversation_key = nip44_conversation_key(&sec2, &public_key(&sec1));
+        assert_eq!(nip44_decrypt(&conversation_key, &payload), text);
+    }
+
+    #[test]
+    fn nip04_round_trip() {
+        let sec1 = secret_key("5c0c523f52a5b6fad39ed2403092df8cebc36318b39383bca6c00808626fab3a");
+        let sec2 = secret_key("4b22aa260e4acb7021e32f38a6cdf4b673c6a277755bfce287e370c924dc936d");
+        let text = "Magma order 1234: Opened a 1000000 sats channel";
+
+        let payload = nip04_encrypt(&sec1, &public_key(&sec2), text).unwrap();
+        assert_eq!(nip04_decrypt(&sec2, &public_key(&sec1), &payload), text);
+    }
+}
@@ -0,0 +1,50 @@
+use reqwest::Client;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::{Event, Notifier};
+
+/// Messages from a Telegram bot, see https://core.telegram.org/bots#how-do-i-create-a-bot
+#[derive(Debug, Deserialize, Clone)]
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+pub struct Telegram {
+    config: TelegramConfig,
+    client: Client,
+}
+
+impl Telegram {
+    pub fn new(config: TelegramConfig) -> Self {
+        Telegram {
+            config,
+            client: Client::new(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for Telegram {
+    async fn notify(&self, event: &Event) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!(
+            "https://api.telegram.org/bot{}/sendMessage",
+            self.config.bot_token
+        );
+
+        // The URL holds the bot token, keep it out of the errors that get logged
+        self.client
+            .post(url)
+            .json(&json!({
+                "chat_id": self.config.chat_id,
+                "text": event.to_string(),
+            }))
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| e.without_url())?;
+
+        Ok(())
+    }
+}
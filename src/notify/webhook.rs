@@ -0,0 +1,38 @@
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::{Event, Notifier};
+
+/// Events POSTed as JSON: `{"kind": ..., "order_id": ..., "message": ...}`
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookConfig {
+    pub url: String,
+}
+
+pub struct Webhook {
+    url: String,
+    client: Client,
+}
+
+impl Webhook {
+    pub fn new(config: WebhookConfig) -> Self {
+        Webhook {
+            url: config.url,
+            client: Client::new(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Notifier for Webhook {
+    async fn notify(&self, event: &Event) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.client
+            .post(&self.url)
+            .json(event)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
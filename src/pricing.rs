@@ -0,0 +1,146 @@
+use serde::Deserialize;
+
+use crate::api::{MarketOffer, OfferChanges};
+
+/// A price that follows the on-chain fee: `base + per_sat_vbyte * fee`,
+/// kept within `min` and `max`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct LinearPrice {
+    pub base: f64,
+    pub per_sat_vbyte: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+impl LinearPrice {
+    pub fn at(&self, sat_per_vbyte: u8) -> f64 {
+        let price = self.base + self.per_sat_vbyte.unwrap_or(0.0) * sat_per_vbyte as f64;
+        clamp(price, self.min, self.max)
+    }
+}
+
+/// Offer `total_size` from the wallet's spendable balance.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TotalSizeConfig {
+    /// Sats kept out of the offer, e.g. for fees or other channels
+    pub reserve: Option<f64>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PricingConfig {
+    /// Magma id of the offer to price. Default is our first channel sell offer
+    pub offer_id: Option<String>,
+    /// Seconds between repricing. Default is 600
+    pub interval: Option<u64>,
+    /// Relative change needed before pushing a new value. Default is 0.1 (10%)
+    pub hysteresis: Option<f64>,
+    pub fee_rate: Option<LinearPrice>,
+    pub base_fee: Option<LinearPrice>,
+    pub onchain_multiplier: Option<LinearPrice>,
+    pub total_size: Option<TotalSizeConfig>,
+}
+
+impl PricingConfig {
+    /// Offer parameters that moved further than the hysteresis from their
+    /// current value, given the on-chain fee and our spendable balance.
+    pub fn reprice(&self, offer: &MarketOffer, sat_per_vbyte: u8, spendable: i64) -> OfferChanges {
+        let hysteresis = self.hysteresis.unwrap_or(0.1);
+        let target = |price: &Option<LinearPrice>, current: f64| {
+            price
+                .as_ref()
+                .map(|price| price.at(sat_per_vbyte).round())
+                .filter(|target| has_moved(current, *target, hysteresis))
+        };
+
+        let total_size = self.total_size.as_ref().and_then(|config| {
+            let target = clamp(
+                spendable as f64 - config.reserve.unwrap_or(0.0),
+                config.min,
+                config.max,
+            );
+            let current = offer.total_size.parse().unwrap_or_default();
+            Some(target.max(0.0).floor()).filter(|target| has_moved(current, *target, hysteresis))
+        });
+
+        let onchain_multiplier = self.onchain_multiplier.as_ref().and_then(|price| {
+            // Multipliers are small numbers, keep two decimals
+            let target = (price.at(sat_per_vbyte) * 100.0).round() / 100.0;
+            let current = offer.onchain_multiplier.unwrap_or_default();
+            Some(target).filter(|target| has_moved(current, *target, hysteresis))
+        });
+
+        OfferChanges {
+            total_size,
+            fee_rate: target(&self.fee_rate, offer.fee_rate),
+            base_fee: target(&self.base_fee, offer.base_fee),
+            onchain_multiplier,
+            ..Default::default()
+        }
+    }
+}
+
+fn clamp(value: f64, min: Option<f64>, max: Option<f64>) -> f64 {
+    let value = min.map_or(value, |min| value.max(min));
+    max.map_or(value, |max| value.min(max))
+}
+
+fn has_moved(current: f64, target: f64, hysteresis: f64) -> bool {
+    if current == 0.0 {
+        return target != 0.0;
+    }
+    ((target - current) / current).abs() > hysteresis
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(min: Option<f64>, max: Option<f64>) -> LinearPrice {
+        LinearPrice {
+            base: 1000.0,
+            per_sat_vbyte: Some(100.0),
+            min,
+            max,
+        }
+    }
+
+    #[test]
+    fn price_follows_the_fee_rate() {
+        assert_eq!(price(None, None).at(0), 1000.0);
+        assert_eq!(price(None, None).at(10), 2000.0);
+    }
+
+    #[test]
+    fn price_is_clamped() {
+        let price = price(Some(1500.0), Some(3000.0));
+
+        assert_eq!(price.at(1), 1500.0);
+        assert_eq!(price.at(5), 1500.0);
+        assert_eq!(price.at(10), 2000.0);
+        assert_eq!(price.at(20), 3000.0);
+        assert_eq!(price.at(u8::MAX), 3000.0);
+    }
+
+    #[test]
+    fn clamp_bounds_are_optional() {
+        assert_eq!(clamp(5.0, None, None), 5.0);
+        assert_eq!(clamp(5.0, Some(10.0), None), 10.0);
+        assert_eq!(clamp(5.0, None, Some(1.0)), 1.0);
+    }
+
+    #[test]
+    fn clamp_prefers_max_when_bounds_cross() {
+        assert_eq!(clamp(5.0, Some(10.0), Some(1.0)), 1.0);
+    }
+
+    #[test]
+    fn has_moved_beyond_hysteresis() {
+        assert!(!has_moved(100.0, 110.0, 0.1));
+        assert!(has_moved(100.0, 111.0, 0.1));
+        assert!(has_moved(100.0, 89.0, 0.1));
+        assert!(!has_moved(0.0, 0.0, 0.1));
+        assert!(has_moved(0.0, 1.0, 0.1));
+    }
+}
@@ -0,0 +1,81 @@
+use serde::Deserialize;
+use std::fmt;
+
+/// Blocks mined in a year, on average
+const BLOCKS_PER_YEAR: f64 = 52_560.0;
+
+/// Cooperative close: overhead, the 2-of-2 funding input with its witness,
+/// and one output per side.
+const CLOSE_TX_VSIZE: f64 = 10.5 + 96.5 + 2.0 * 43.0;
+
+#[derive(Debug, Default, Deserialize, Clone)]
+pub struct ProfitabilityConfig {
+    /// Yearly cost of the capital locked in a channel, e.g. 0.05 for 5%. Default is 0
+    pub annual_capital_cost: Option<f64>,
+    /// Fee rate (sat/vB) expected when closing the channel. Default is the opening fee rate
+    pub close_fee_rate: Option<f64>,
+    /// Routing revenue expected per year, in ppm of the channel size. Default is 0
+    pub routing_revenue_ppm: Option<f64>,
+    /// Minimum profit (sats) required to open a channel. Default is 0
+    pub min_margin: Option<f64>,
+}
+
+/// What the bot knows about an order when deciding to accept it.
+pub struct OrderTerms {
+    pub size: i64,
+    /// What we get paid, already net of the Amboss fee
+    pub seller_invoice_amount: i64,
+    pub min_block_length: f64,
+}
+
+/// Expected revenue and costs of selling a channel, in sats.
+#[derive(Debug)]
+pub struct Estimate {
+    pub revenue: f64,
+    pub open_fee: f64,
+    pub close_fee: f64,
+    pub capital_cost: f64,
+    pub routing_revenue: f64,
+}
+
+impl Estimate {
+    pub fn profit(&self) -> f64 {
+        self.revenue - self.open_fee - self.close_fee - self.capital_cost + self.routing_revenue
+    }
+}
+
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "revenue {:.0}, open fee {:.0}, close fee {:.0}, capital cost {:.0}, routing revenue {:.0}",
+            self.revenue,
+            self.open_fee,
+            self.close_fee,
+            self.capital_cost,
+            self.routing_revenue
+        )
+    }
+}
+
+impl ProfitabilityConfig {
+    /// Estimates the order's profit given the fee of the funding transaction.
+    pub fn estimate(&self, terms: &OrderTerms, open_fee: f64, sat_per_vbyte: u8) -> Estimate {
+        let years = terms.min_block_length / BLOCKS_PER_YEAR;
+        let size = terms.size as f64;
+        let revenue = terms.seller_invoice_amount as f64;
+
+        Estimate {
+            revenue,
+            open_fee,
+            close_fee: CLOSE_TX_VSIZE * self.close_fee_rate.unwrap_or(sat_per_vbyte as f64),
+            capital_cost: size * self.annual_capital_cost.unwrap_or(0.0) * years,
+            routing_revenue: size * self.routing_revenue_ppm.unwrap_or(0.0) / 1_000_000.0 * years,
+        }
+    }
+
+    /// Whether the estimated profit clears the configured margin.
+    pub fn is_profitable(&self, estimate: &Estimate) -> bool {
+        estimate.profit() >= self.min_margin.unwrap_or(0.0)
+    }
+}
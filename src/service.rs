@@ -1,102 +1,439 @@
 use lnd_grpc_rust::lnrpc;
 use lnd_grpc_rust::lnrpc::channel_point::FundingTxid;
 use log::{debug, error, info, warn};
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::env;
-use tokio::time::{sleep, Duration};
+use tokio::sync::mpsc;
+use tokio::time::{sleep_until, Duration, Instant};
+use tracing::{info_span, Instrument, Span};
 
-use crate::api::orders::OrderStatus;
+use crate::api::orders::{OrderStatus, OrdersGetUserMarketOfferOrdersList};
+use crate::approval::{ApprovalConfig, Approvals, Decision};
+use crate::bumping::FeeBumpingConfig;
+use crate::chat::ChatConfig;
+use crate::clock::now;
 use crate::config;
-use crate::errors::ForbiddenError;
+use crate::control::{Command, Control};
+use crate::errors::{ForbiddenError, InsufficientFundsError, MagmaError, RateLimitedError};
+use crate::health::Health;
+use crate::http::{self, HttpConfig};
 use crate::mempool;
+use crate::metrics::Metrics;
+use crate::node::format_outpoint;
+use crate::notify::{Event, EventKind, Notifications};
+use crate::pricing::PricingConfig;
+use crate::profitability::{Estimate, OrderTerms, ProfitabilityConfig};
+use crate::store::Store;
 use crate::{api::Api, node::LNNode};
 
 use crate::api::cancel_order::OrderCancellationReason;
 
+use lifecycle::{action, Action};
+
+mod approvals;
+mod channels;
+mod chat;
+mod control;
+mod fees;
+mod funding;
+mod invoices;
+mod lifecycle;
+mod offers;
+mod operator;
+mod reservations;
+
+pub use operator::{open_cost, OpenCost};
+
+/// Consecutive loop failures before notifying about them. The alert repeats
+/// each time the count doubles, until a loop succeeds again.
+const LOOP_FAILURES_ALERT: u32 = 3;
+
+/// Longest pause when Amboss rate limits us.
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(3600);
+
+/// Streams from LND the service reacts to between polls.
+struct Subscriptions {
+    settled_invoices: mpsc::UnboundedReceiver<lnrpc::Invoice>,
+    channel_events: mpsc::UnboundedReceiver<lnrpc::ChannelEventUpdate>,
+    decisions: mpsc::UnboundedReceiver<(String, Decision)>,
+    commands: mpsc::UnboundedReceiver<Command>,
+}
+
 pub struct Service {
     node: LNNode,
     api: Api,
     interval: Option<u64>,
+    enforce_fee_caps: bool,
+    base_fee_cap_unit: config::BaseFeeUnit,
+    pricing: Option<PricingConfig>,
+    guard_liquidity: bool,
+    profitability: ProfitabilityConfig,
+    fee_bumping: Option<FeeBumpingConfig>,
+    chat: Option<ChatConfig>,
+    approval: Option<ApprovalConfig>,
+    approvals: Approvals,
+    control: Control,
+    http: Option<HttpConfig>,
+    metrics: Metrics,
+    health: Health,
+    notifications: Notifications,
+    last_priced: RefCell<Option<Instant>>,
+    last_tracked: RefCell<Option<Instant>>,
+    // Identity of our node, fetched on first use
+    own_pubkey: RefCell<Option<String>>,
+    // Orders waiting for their invoice to be paid, keyed by payment hash
+    pending_invoices: RefCell<HashMap<Vec<u8>, OrdersGetUserMarketOfferOrdersList>>,
+    store: RefCell<Store>,
 }
 
 impl Service {
-    pub async fn new() -> Self {
-        let config = config::load().expect("Failed to load config");
+    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        let config = config::load()?;
 
-        let node = LNNode::new(config.lnd)
-            .await
-            .expect("Failed to create LNNode");
+        let node = LNNode::new(config.lnd).await?;
 
         let missing_api_key = config.magma.api_key.is_none();
         let mut api = Api::new(config.magma);
 
         if missing_api_key {
             if let Err(e) = api.load_api_key_from_file() {
-                api.gen_new_api_key(&node).await.unwrap();
+                info!("No saved Magma API key ({}), logging in with the node", e);
+                api.gen_new_api_key(&node).await?;
             }
         }
 
-        Self {
+        let store = Store::load()?;
+        let notifications = Notifications::new(config.notifications.unwrap_or_default())?;
+
+        Ok(Self {
             node,
             api,
             interval: config.loop_interval,
-        }
+            enforce_fee_caps: config.enforce_fee_caps.unwrap_or(true),
+            base_fee_cap_unit: config.base_fee_cap_unit.unwrap_or_default(),
+            pricing: config.pricing,
+            guard_liquidity: config.guard_liquidity.unwrap_or(true),
+            profitability: config.profitability.unwrap_or_default(),
+            fee_bumping: config.fee_bumping,
+            chat: config.chat,
+            approval: config.approval,
+            approvals: Approvals::default(),
+            control: Control::default(),
+            http: config.http.clone(),
+            notifications,
+            metrics: Metrics::new()?,
+            health: Health::new(
+                config.loop_interval.unwrap_or(60).max(10),
+                config
+                    .http
+                    .as_ref()
+                    .and_then(|http| http.ready_intervals)
+                    .unwrap_or(3),
+            ),
+            last_priced: RefCell::new(None),
+            last_tracked: RefCell::new(None),
+            own_pubkey: RefCell::new(None),
+            pending_invoices: RefCell::new(HashMap::new()),
+            store: RefCell::new(store),
+        })
     }
 
     async fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
         debug!("Checking orders...");
 
-        let orders = self.api.get_orders().await?;
-
-        for order in orders {
-            let result = async {
-                match order.status {
-                    OrderStatus::WAITING_FOR_CHANNEL_OPEN => {
-                        info!("Opening channel for order: {}", order.id);
-                        self.open_channel(&order).await?;
-                    }
+        let mut orders = self.api.get_actionable_orders().await?;
+        orders.extend(self.get_tracked_orders(&orders).await);
+        self.metrics.last_successful_poll.set(now() as i64);
 
-                    OrderStatus::WAITING_FOR_SELLER_APPROVAL => {
-                        info!("Approving order: {}", order.id);
-                        self.process_new_order(&order).await?;
-                    }
+        if let Err(e) = self.guard_offer_liquidity(&orders).await {
+            error!("Error checking offer liquidity: {:?}", e);
+        }
 
-                    _ => {
-                        debug!("Skipping order: {} ({:?})", order.id, order.status);
-                    }
-                }
-                Ok::<(), Box<dyn std::error::Error>>(())
-            }
-            .await;
+        for order in orders {
+            let result = self
+                .handle_order(&order)
+                .instrument(order_span(&order))
+                .await;
 
             if let Err(e) = result {
+                if e.is::<RateLimitedError>() {
+                    return Err(e);
+                }
                 error!("Error processing order {}: {:?}", order.id, e);
             }
         }
 
+        if let Err(e) = self.expire_reservations() {
+            error!("Error expiring reservations: {:?}", e);
+        }
+
+        if let Err(e) = self.check_leases().await {
+            error!("Error checking channel leases: {:?}", e);
+        }
+
+        if let Err(e) = self.bump_stuck_fundings().await {
+            error!("Error bumping funding transactions: {:?}", e);
+        }
+
+        if let Err(e) = self.enforce_fee_caps().await {
+            error!("Error enforcing fee caps: {:?}", e);
+        }
+
+        if let Err(e) = self.reprice_offer().await {
+            error!("Error repricing offer: {:?}", e);
+        }
+
         Ok(())
     }
 
     pub async fn start(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let adjustments = self.store.borrow().offer_adjustments.clone();
+        if let Err(e) = self.api.reconcile_offers(&adjustments).await {
+            error!("Error reconciling offers: {:?}", e);
+        }
+
+        if self.store.borrow().paused {
+            info!("Processing stays paused, as the operator left it");
+            self.control.set_paused(true);
+            self.health.set_paused(true);
+        }
+
+        let mut subscriptions = Subscriptions {
+            settled_invoices: self
+                .node
+                .subscribe_invoices(self.store.borrow().last_settle_index),
+            channel_events: self.node.subscribe_channel_events(),
+            decisions: self.approvals.subscribe(),
+            commands: self.control.subscribe(),
+        };
+
+        if let Some(config) = &self.http {
+            tokio::spawn(http::serve(
+                config.clone(),
+                self.metrics.clone(),
+                self.health.clone(),
+                self.approvals.clone(),
+                self.control.clone(),
+            ));
+        }
+
+        let mut failures = 0;
+
         loop {
-            match self.run().await {
+            self.check_lnd().await;
+
+            if self.control.is_paused() {
+                debug!("Processing paused");
+                let interval = self.interval.unwrap_or(60).max(10);
+                self.wait(Duration::from_secs(interval), &mut subscriptions)
+                    .await;
+                continue;
+            }
+
+            let timer = self.metrics.loop_duration.start_timer();
+            let result = self.run().await;
+            timer.observe_duration();
+            self.update_api_metrics();
+            self.update_health(&result);
+
+            match result {
                 Ok(_) => {
+                    if failures >= LOOP_FAILURES_ALERT {
+                        self.notify(Event::new(
+                            EventKind::LoopRecovered,
+                            None,
+                            format!("Loop works again after {} failures", failures),
+                        ));
+                    }
+                    failures = 0;
                     let interval = self.interval.unwrap_or(60).max(10);
                     debug!("Sleeping for {} seconds...", interval);
-                    sleep(Duration::from_secs(interval)).await;
+                    self.wait(Duration::from_secs(interval), &mut subscriptions)
+                        .await;
                 }
                 Err(e) => {
+                    failures += 1;
+                    if failures % LOOP_FAILURES_ALERT == 0
+                        && (failures / LOOP_FAILURES_ALERT).is_power_of_two()
+                    {
+                        self.notify(Event::new(
+                            EventKind::LoopFailing,
+                            None,
+                            format!("{} loops failed in a row, last error: {}", failures, e),
+                        ));
+                    }
+
                     if e.is::<ForbiddenError>() {
                         self.api.gen_new_api_key(&self.node).await?;
+                        self.notify(Event::new(
+                            EventKind::ApiKeyRotated,
+                            None,
+                            "Magma API key rejected, created a new one",
+                        ));
+                    } else if let Some(limited) = e.downcast_ref::<RateLimitedError>() {
+                        let retry_after = limited.retry_after.min(MAX_RATE_LIMIT_WAIT);
+                        warn!("{}, pausing for {}s", limited, retry_after.as_secs());
+                        self.wait(retry_after, &mut subscriptions).await;
+                    }
+                }
+            }
+        }
+    }
+
+    fn notify(&self, event: Event) {
+        self.notifications.notify(event);
+    }
+
+    async fn check_lnd(&self) {
+        let result = match self.node.get_info().await {
+            Ok(info) => Ok(format!(
+                "{} at height {}, synced to chain: {}",
+                info.alias, info.block_height, info.synced_to_chain
+            )),
+            Err(e) => Err(e.to_string()),
+        };
+        self.health.set_lnd(result);
+    }
+
+    /**
+     * Records the state of Magma after a run. Only errors coming from the
+     * Magma API count against it: LND is checked on its own before each run,
+     * and other failures only show in the age of the last successful run.
+     */
+    fn update_health(&self, result: &Result<(), Box<dyn std::error::Error>>) {
+        let expiry = self.api.api_key_expiry();
+        let magma = match result {
+            Err(e) if e.is::<ForbiddenError>() => Err("API key rejected".to_string()),
+            _ if expiry.is_some_and(|expiry| expiry <= now() as i64) => {
+                Err("API key expired".to_string())
+            }
+            Err(e) if e.is::<RateLimitedError>() => Ok(e.to_string()),
+            Err(e) if e.is::<MagmaError>() => Err(e.to_string()),
+            _ => Ok(match expiry {
+                Some(expiry) => format!("API key valid until {}", expiry),
+                None => "API key valid".to_string(),
+            }),
+        };
+        self.health.set_magma(magma);
+
+        if result.is_ok() {
+            self.health.run_succeeded();
+        }
+    }
+
+    fn update_api_metrics(&self) {
+        if let Some(budget) = self.api.query_budget() {
+            self.metrics.query_cost_remaining.set(budget.current());
+        }
+        if let Some(expiry) = self.api.api_key_expiry() {
+            self.metrics.api_key_expiry.set(expiry);
+        }
+    }
+
+    /// Sleeps until the next poll, handling LND events and operator requests as they arrive.
+    async fn wait(&self, interval: Duration, subscriptions: &mut Subscriptions) {
+        let deadline = Instant::now() + interval;
+
+        loop {
+            tokio::select! {
+                _ = sleep_until(deadline) => return,
+                Some(invoice) = subscriptions.settled_invoices.recv() => {
+                    self.on_invoice_settled(invoice).await;
+                }
+                Some(event) = subscriptions.channel_events.recv() => {
+                    self.on_channel_event(event).await;
+                }
+                Some(command) = subscriptions.commands.recv() => {
+                    self.on_command(command).await;
+                }
+                Some((order_id, decision)) = subscriptions.decisions.recv() => {
+                    if let Err(e) = self.decide(&order_id, decision).await {
+                        error!("Error carrying out decision on order {}: {:?}", order_id, e);
                     }
                 }
             }
         }
     }
 
+    /**
+     * Opens the channel as soon as the invoice of an accepted order is paid,
+     * instead of waiting for the next poll to see WAITING_FOR_CHANNEL_OPEN.
+     * Invoices accepted before a restart, replayed from the last settle index
+     * seen, are matched to their order by payment hash.
+     */
+    async fn on_invoice_settled(&self, invoice: lnrpc::Invoice) {
+        let pending = self.pending_invoices.borrow_mut().remove(&invoice.r_hash);
+
+        let order = match pending {
+            Some(order) => order,
+            None => {
+                let payment_hash = hex::encode(&invoice.r_hash);
+                let order_id = self
+                    .store
+                    .borrow()
+                    .orders
+                    .iter()
+                    .find(|(_, record)| record.payment_hash.as_ref() == Some(&payment_hash))
+                    .map(|(order_id, _)| order_id.clone());
+                let Some(order_id) = order_id else {
+                    debug!("Settled invoice {} is not a Magma order", payment_hash);
+                    return;
+                };
+
+                match self.order(&order_id).await {
+                    Ok(order) if action(&order.status) == Action::OpenChannel => order,
+                    Ok(order) => {
+                        debug!(
+                            "Invoice for order {} settled, which is now {:?}",
+                            order.id, order.status
+                        );
+                        self.save_settle_index(invoice.settle_index);
+                        return;
+                    }
+                    // The next poll opens its channel if it's paid
+                    Err(e) => {
+                        error!("Error fetching order {}: {:?}", order_id, e);
+                        return;
+                    }
+                }
+            }
+        };
+
+        info!("Invoice for order {} settled", order.id);
+        self.save_settle_index(invoice.settle_index);
+        if self.control.is_paused() {
+            info!(
+                "Processing paused, the channel of order {} opens once resumed",
+                order.id
+            );
+            return;
+        }
+
+        if let Err(e) = self
+            .open_channel(&order)
+            .instrument(order_span(&order))
+            .await
+        {
+            error!("Error processing order {}: {:?}", order.id, e);
+        }
+    }
+
+    /// Records the settle index of an order invoice, to resume the subscription from.
+    fn save_settle_index(&self, settle_index: u64) {
+        let mut store = self.store.borrow_mut();
+        if settle_index > store.last_settle_index {
+            store.last_settle_index = settle_index;
+            if let Err(e) = store.save() {
+                error!("Error saving state: {}", e);
+            }
+        }
+    }
+
     /**
      * 1. Get current fee rate
      * 2. Calculate UTXOs required and fees
-     * 3. Ensure it's profitable
+     * 3. Estimate the profit
      * 4. Create channel
      * 5. Confirm channel open
      *
@@ -107,6 +444,25 @@ impl Service {
         &self,
         order: &crate::api::orders::OrdersGetUserMarketOfferOrdersList,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        // The channel may already be open if the invoice settlement got here
+        // first, or if a previous confirmation failed. Only confirm it then.
+        let opened = self
+            .store
+            .borrow()
+            .channels
+            .get(&order.id)
+            .map(|channel| channel.channel_point.clone());
+        if let Some(tx_point) = opened {
+            debug!(
+                "Channel already opened for order {}: {}",
+                order.id, tx_point
+            );
+            return self
+                .api
+                .confirm_channel_open(order.id.as_str(), tx_point.as_str())
+                .await;
+        }
+
         info!("Opening channel for order: {}", order.id);
 
         let channel_size: i64 = order.size.parse().unwrap();
@@ -117,25 +473,79 @@ impl Service {
         debug!("Current fee rate: {}", sat_per_vbyte);
 
         // 2. Calculate UTXOs required and fees
-        let utxos = self.node.list_unspent().await?;
-        let outpoints = calculate_utxos_required_and_fees(channel_size, sat_per_vbyte, utxos)?;
+        // Reserved UTXOs are hidden from the wallet, give them back to spend
+        // them. They go first, topped up with others if fees rose since
+        let reservation = self.release_reservation(&order.id, "opening channel").await;
+        let selected = match self.node.list_unspent().await {
+            Ok(mut utxos) => {
+                if let Some(reservation) = &reservation {
+                    reserved_first(&mut utxos, &reservation.outpoints);
+                }
+                calculate_utxos_required_and_fees(channel_size, sat_per_vbyte, utxos)
+            }
+            Err(e) => Err(e),
+        };
+        let outpoints = match selected {
+            Ok(outpoints) => outpoints,
+            Err(e) if e.is::<InsufficientFundsError>() => {
+                // Funds may confirm or come back before the order times out,
+                // retry on the next polls until it's about to
+                let interval = self.interval.unwrap_or(60).max(10);
+                let deadline = invoices::order_timeout(order.timeout.as_deref());
+                if deadline.is_none_or(|deadline| now() + interval < deadline) {
+                    warn!(
+                        "Can't fund channel of order {} yet, retrying. {}",
+                        order.id, e
+                    );
+                    if let Some(reservation) = reservation {
+                        self.restore_reservation(&order.id, reservation).await;
+                    }
+                    return Err(e);
+                }
+
+                warn!(
+                    "Can't fund channel of order {} before it times out, canceling it. {}",
+                    order.id, e
+                );
+                self.api
+                    .cancel_order(order.id.as_str(), OrderCancellationReason::UNABLE_TO_PAY)
+                    .await?;
+                self.metrics
+                    .orders_cancelled
+                    .with_label_values(&["UNABLE_TO_PAY"])
+                    .inc();
+                self.notify(Event::new(
+                    EventKind::OrderCancelled,
+                    Some(&order.id),
+                    format!("Cancelled, can't fund the channel. {}", e),
+                ));
+                return Err(e);
+            }
+            Err(e) => {
+                if let Some(reservation) = reservation {
+                    self.restore_reservation(&order.id, reservation).await;
+                }
+                return Err(e);
+            }
+        };
         debug!("Using {} UTXOs: {:?}", outpoints.len(), outpoints);
 
-        // 3. Ensure it's profitable
+        // 3. Estimate the profit, checked when the order was accepted
         let fee = calc_fee(outpoints.len(), sat_per_vbyte);
-        let order_cost = order
-            .seller_invoice_amount
-            .as_ref()
-            .map(|s| s.parse::<i64>().unwrap())
-            .unwrap();
-
-        if fee > order_cost as f64 {
-            Err(format!(
-                "Fee is higher than order cost. Fee: {}, Order cost: {}",
-                fee, order_cost
-            ))?;
-        }
-        info!("Expected profit: {} sats", order_cost as f64 - fee);
+        let estimate = match self.estimate_profit(order, outpoints.len(), sat_per_vbyte) {
+            Ok(estimate) => estimate,
+            Err(e) => {
+                if let Some(reservation) = reservation {
+                    self.restore_reservation(&order.id, reservation).await;
+                }
+                return Err(e);
+            }
+        };
+        info!(
+            "Expected profit: {:.0} sats ({})",
+            estimate.profit(),
+            estimate
+        );
 
         // 4. Create channel
         match self
@@ -146,7 +556,10 @@ impl Service {
             Ok(channel_point) => {
                 // Handle success
                 let tx_hex = match &channel_point.funding_txid {
-                    Some(FundingTxid::FundingTxidBytes(bytes)) => hex::encode(bytes),
+                    // LND returns the txid bytes in internal (reversed) order
+                    Some(FundingTxid::FundingTxidBytes(bytes)) => {
+                        hex::encode(bytes.iter().rev().copied().collect::<Vec<u8>>())
+                    }
                     Some(FundingTxid::FundingTxidStr(txid_str)) => txid_str.clone(),
                     None => Err("No funding txid")?,
                 };
@@ -154,18 +567,74 @@ impl Service {
                 let tx_point = format!("{}:{}", tx_hex, channel_point.output_index);
                 info!("Channel opened: https://mempool.space/tx/{}", tx_point);
 
+                self.metrics.channels_opened.inc();
+                self.metrics.sats_committed.inc_by(channel_size as u64);
+                self.metrics.fees_paid.inc_by(fee);
+                self.metrics.expected_profit.set(estimate.profit());
+                self.notify(Event::new(
+                    EventKind::ChannelOpened,
+                    Some(&order.id),
+                    format!(
+                        "Opened a {} sats channel to {}: {}. Expected profit: {:.0} sats",
+                        channel_size,
+                        order.account,
+                        tx_point,
+                        estimate.profit()
+                    ),
+                ));
+
+                if let Err(e) = self.record_sold_channel(order, &tx_point) {
+                    error!("Error saving channel of order {}: {:?}", order.id, e);
+                }
+
                 // 5. Confirm channel open
                 self.api
                     .confirm_channel_open(order.id.as_str(), tx_point.as_str())
-                    .await
+                    .await?;
+                self.send_chat(
+                    order,
+                    ChatConfig::channel_opened,
+                    &[
+                        ("txid", tx_hex.as_str()),
+                        ("channel_point", tx_point.as_str()),
+                    ],
+                )
+                .await;
+
+                Ok(())
             }
             Err(e) => {
+                if let Some(reservation) = reservation {
+                    self.restore_reservation(&order.id, reservation).await;
+                }
                 self.cancel_if_buyer_offline(order).await?;
                 Err(e)
             }
         }
     }
 
+    /// Expected profit of an order whose channel is funded with `inputs` UTXOs.
+    fn estimate_profit(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+        inputs: usize,
+        sat_per_vbyte: u8,
+    ) -> Result<Estimate, Box<dyn std::error::Error>> {
+        let terms = OrderTerms {
+            size: order.size.parse()?,
+            seller_invoice_amount: order
+                .seller_invoice_amount
+                .as_deref()
+                .ok_or("Order has no seller invoice amount")?
+                .parse()?,
+            min_block_length: order.locked_min_block_length,
+        };
+
+        Ok(self
+            .profitability
+            .estimate(&terms, calc_fee(inputs, sat_per_vbyte), sat_per_vbyte))
+    }
+
     async fn check_buyer_is_online(&self, pubkey: &str) -> Result<(), Box<dyn std::error::Error>> {
         let addresses = self.api.get_node_addresses(&pubkey).await?;
         let addr = addresses.first().unwrap();
@@ -192,6 +661,16 @@ impl Service {
                     OrderCancellationReason::UNABLE_TO_CONNECT_TO_NODE,
                 )
                 .await?;
+            self.metrics
+                .orders_cancelled
+                .with_label_values(&["UNABLE_TO_CONNECT_TO_NODE"])
+                .inc();
+            self.notify(Event::new(
+                EventKind::OrderCancelled,
+                Some(&order.id),
+                format!("Cancelled, can't connect to buyer's node. {}", e),
+            ));
+            self.release_reservation(&order.id, "order cancelled").await;
 
             return Err(e);
         }
@@ -199,105 +678,241 @@ impl Service {
         Ok(())
     }
 
+    /// Rejects the order if we can't connect to the buyer's node. Returns
+    /// whether it was rejected.
     async fn reject_if_buyer_offline(
         &self,
         order: &crate::api::orders::OrdersGetUserMarketOfferOrdersList,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+    ) -> Result<bool, Box<dyn std::error::Error>> {
         let pubkey: String = order.account.clone();
 
         let buyer_info = self.check_buyer_is_online(&pubkey).await;
 
         if let Err(e) = buyer_info {
             warn!("Can't connect to buyer's node, rejecting order. {}", e);
+            self.send_chat(
+                order,
+                ChatConfig::rejected,
+                &[("reason", "we can't connect to your node")],
+            )
+            .await;
             // Add the missing line to reject the order
             self.api.reject_order(order.id.as_str()).await?;
+            self.metrics
+                .orders_rejected
+                .with_label_values(&["buyer_offline"])
+                .inc();
+            self.notify(Event::new(
+                EventKind::OrderRejected,
+                Some(&order.id),
+                format!("Rejected, can't connect to buyer's node. {}", e),
+            ));
+            return Ok(true);
         }
 
-        Ok(())
+        Ok(false)
     }
 
     /**
-     * 1. Get buyer's address
-     * 2. Make sure we can connect to buyer's node
+     * 1. Make sure we can connect to buyer's node, unless disabled
+     *  - If not, reject order
+     * 2. Pick the UTXOs for the channel
+     *  - If there aren't enough, reject order
+     * 3. Ensure it's profitable
      *  - If not, reject order
-     * 3. Create invoice
-     * 4. Accept order
+     * 4. Reserve the funds for the channel
+     * 5. Create invoice
+     * 6. Accept order
      *
      * @param order
+     * @param reason why it's accepted, as labelled in the metrics
      * @returns
      */
     async fn process_new_order(
         &self,
         order: &crate::api::orders::OrdersGetUserMarketOfferOrdersList,
+        reason: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
         let reject_if_off = env::var("REJECT_IF_BUYER_OFFLINE")
             .map(|val| val == "true")
             .unwrap_or(true);
 
         info!("Processing new order: {}", order.id);
+
+        // 1. Make sure we can connect to buyer's node, unless disabled
+        //  - If not, reject order
         if reject_if_off {
-            self.reject_if_buyer_offline(order).await?;
+            if self.reject_if_buyer_offline(order).await? {
+                return Ok(());
+            }
         } else {
             info!("Skipping buyer's node check");
         }
 
-        // 3. Create invoice
-        let order_cost = order
-            .seller_invoice_amount
-            .as_ref()
-            .unwrap()
-            .parse::<i64>()
-            .unwrap();
-        let invoice = self.node.create_invoice(order_cost, 172800).await?;
-        debug!("Invoice created: {}", invoice);
-
-        // 4. Accept order
-        self.api
-            .accept_order(order.id.as_str(), invoice.as_str())
-            .await?;
+        // 2. Pick the UTXOs for the channel
+        //  - If there aren't enough, reject order
+        let channel_size: i64 = order.size.parse()?;
+        let sat_per_vbyte = mempool::get_fastest_fee().await?;
+        let utxos = self.node.list_unspent().await?;
+        let outpoints = match calculate_utxos_required_and_fees(channel_size, sat_per_vbyte, utxos)
+        {
+            Ok(outpoints) => outpoints,
+            Err(e) if e.is::<InsufficientFundsError>() => {
+                warn!("Can't fund order {}, rejecting it. {}", order.id, e);
+                self.send_chat(
+                    order,
+                    ChatConfig::rejected,
+                    &[("reason", "we don't have the funds for it right now")],
+                )
+                .await;
+                self.api.reject_order(order.id.as_str()).await?;
+                self.metrics
+                    .orders_rejected
+                    .with_label_values(&["insufficient_funds"])
+                    .inc();
+                self.notify(Event::new(
+                    EventKind::InsufficientFunds,
+                    Some(&order.id),
+                    format!("Rejected, can't fund it. {}", e),
+                ));
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        // 3. Ensure it's profitable
+        //  - If not, reject order
+        let estimate = self.estimate_profit(order, outpoints.len(), sat_per_vbyte)?;
+        if !self.profitability.is_profitable(&estimate) {
+            warn!(
+                "Order {} is not profitable, rejecting it. Expected profit: {:.0} sats ({})",
+                order.id,
+                estimate.profit(),
+                estimate
+            );
+            self.send_chat(
+                order,
+                ChatConfig::rejected,
+                &[("reason", "it isn't worth it at the current on-chain fees")],
+            )
+            .await;
+            self.api.reject_order(order.id.as_str()).await?;
+            self.metrics
+                .orders_rejected
+                .with_label_values(&["unprofitable"])
+                .inc();
+            self.notify(Event::new(
+                EventKind::OrderRejected,
+                Some(&order.id),
+                format!(
+                    "Rejected, not profitable. Expected profit: {:.0} sats",
+                    estimate.profit()
+                ),
+            ));
+            return Ok(());
+        }
+
+        // 4. Reserve the funds for the channel
+        if let Err(e) = self.reserve_funds(order, &outpoints).await {
+            warn!("Could not reserve funds for order {}: {}", order.id, e);
+        }
+
+        let accepted = async {
+            // 5. Create invoice
+            let invoice = self.create_order_invoice(order).await?;
+
+            // 6. Accept order
+            self.api
+                .accept_order(order.id.as_str(), invoice.payment_request.as_str())
+                .await?;
+
+            Ok::<_, Box<dyn std::error::Error>>(invoice)
+        }
+        .await;
+
+        let invoice = match accepted {
+            Ok(invoice) => invoice,
+            Err(e) => {
+                self.release_reservation(&order.id, "acceptance failed")
+                    .await;
+                return Err(e);
+            }
+        };
+
+        self.metrics
+            .orders_accepted
+            .with_label_values(&[reason])
+            .inc();
+        self.notify(Event::new(
+            EventKind::OrderAccepted,
+            Some(&order.id),
+            format!(
+                "Accepted a {} sats channel to {}",
+                order.size, order.account
+            ),
+        ));
+        self.pending_invoices
+            .borrow_mut()
+            .insert(invoice.r_hash, order.clone());
 
         Ok(())
     }
 }
 
+/// Span carrying the order's context to every log line of its processing.
+fn order_span(order: &OrdersGetUserMarketOfferOrdersList) -> Span {
+    info_span!(
+        "order",
+        order_id = %order.id,
+        buyer = %order.account,
+        size = %order.size,
+        status = ?order.status
+    )
+}
+
+/// Moves the reserved UTXOs ahead of the others, keeping the order of each.
+fn reserved_first(utxos: &mut [lnrpc::Utxo], reserved: &[String]) {
+    utxos.sort_by_key(|utxo| {
+        let outpoint = utxo.outpoint.as_ref().map(format_outpoint);
+        !outpoint.is_some_and(|outpoint| reserved.contains(&outpoint))
+    });
+}
+
 fn calculate_utxos_required_and_fees(
     channel_size: i64,
     sat_per_vbyte: u8,
     utxos: Vec<lnrpc::Utxo>,
 ) -> Result<Vec<lnrpc::OutPoint>, Box<dyn std::error::Error>> {
     let total: i64 = utxos.iter().map(|utxo| utxo.amount_sat).sum();
-    let mut amount_remaining = channel_size as f64;
 
     let mut related_outpoints = vec![];
+    let mut amount_selected = 0;
 
     if total < channel_size {
-        return Err(format!(
-            "There are no UTXOs available to open a channel of {} sats. Total UTXOS: {} sats",
-            amount_remaining, total
-        ))?;
+        return Err(InsufficientFundsError {
+            needed: channel_size,
+            available: total,
+        }
+        .into());
     }
 
     for utxo in utxos {
         related_outpoints.push(utxo.outpoint.unwrap().clone());
+        amount_selected += utxo.amount_sat;
 
         let fee_cost = calc_fee(related_outpoints.len(), sat_per_vbyte);
         let amount_with_fees = channel_size as f64 + fee_cost;
 
-        if amount_remaining <= amount_with_fees {
-            amount_remaining = 0.0;
-            break;
+        if amount_selected as f64 >= amount_with_fees {
+            return Ok(related_outpoints);
         }
-        amount_remaining -= utxo.amount_sat as f64;
     }
 
-    if amount_remaining > 0.0 {
-        return Err(format!(
-            "There are no UTXOs available to open a channel of {} sats. Total UTXOS: {} sats short",
-            channel_size, amount_remaining
-        ))?;
+    Err(InsufficientFundsError {
+        needed: channel_size + calc_fee(related_outpoints.len(), sat_per_vbyte).ceil() as i64,
+        available: total,
     }
-
-    Ok(related_outpoints)
+    .into())
 }
 
 fn calc_fee(num_inputs: usize, sat_per_vbyte: u8) -> f64 {
@@ -313,3 +928,100 @@ fn tx_size(utxos_needed: usize) -> f64 {
     let total_size = inputs_size + outputs_size + overhead_size;
     total_size
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utxos(amounts: &[i64]) -> Vec<lnrpc::Utxo> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(index, amount)| lnrpc::Utxo {
+                amount_sat: *amount,
+                outpoint: Some(lnrpc::OutPoint {
+                    txid_str: "00".repeat(32),
+                    output_index: index as u32,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn shortfall(result: Result<Vec<lnrpc::OutPoint>, Box<dyn std::error::Error>>) -> (i64, i64) {
+        let e = result.unwrap_err();
+        let e = e.downcast_ref::<InsufficientFundsError>().unwrap();
+        (e.needed, e.available)
+    }
+
+    #[test]
+    fn fee_grows_with_inputs() {
+        assert_eq!(calc_fee(1, 10), 1540.0);
+        assert_eq!(calc_fee(2, 10), 2115.0);
+    }
+
+    #[test]
+    fn selects_exact_amount_with_fee() {
+        let outpoints = calculate_utxos_required_and_fees(100_000, 10, utxos(&[101_540])).unwrap();
+
+        assert_eq!(outpoints.len(), 1);
+    }
+
+    #[test]
+    fn stops_once_covered() {
+        let outpoints =
+            calculate_utxos_required_and_fees(100_000, 10, utxos(&[200_000, 50_000])).unwrap();
+
+        assert_eq!(outpoints.len(), 1);
+        assert_eq!(outpoints[0].output_index, 0);
+    }
+
+    #[test]
+    fn fails_when_short_of_the_channel_size() {
+        let result = calculate_utxos_required_and_fees(100_000, 10, utxos(&[60_000, 39_999]));
+
+        assert_eq!(shortfall(result), (100_000, 99_999));
+    }
+
+    #[test]
+    fn fails_when_short_of_the_fee() {
+        let result = calculate_utxos_required_and_fees(100_000, 10, utxos(&[101_539]));
+
+        assert_eq!(shortfall(result), (101_540, 101_539));
+    }
+
+    #[test]
+    fn counts_the_fee_of_each_extra_input() {
+        let outpoints =
+            calculate_utxos_required_and_fees(100_000, 10, utxos(&[100_000, 2_115])).unwrap();
+        assert_eq!(outpoints.len(), 2);
+
+        let result = calculate_utxos_required_and_fees(100_000, 10, utxos(&[100_000, 2_114]));
+        assert_eq!(shortfall(result), (102_115, 102_114));
+    }
+
+    #[test]
+    fn fails_without_utxos() {
+        let result = calculate_utxos_required_and_fees(100_000, 10, vec![]);
+
+        assert_eq!(shortfall(result), (100_000, 0));
+    }
+
+    #[test]
+    fn tops_up_reserved_utxos_when_fees_rose() {
+        let mut wallet = utxos(&[50_000, 101_540, 10_000]);
+        let reserved = vec![format!("{}:1", "00".repeat(32))];
+        reserved_first(&mut wallet, &reserved);
+
+        // Enough at the rate of acceptance
+        let outpoints = calculate_utxos_required_and_fees(100_000, 10, wallet.clone()).unwrap();
+        assert_eq!(outpoints.len(), 1);
+        assert_eq!(outpoints[0].output_index, 1);
+
+        // Topped up with the next UTXO at a higher rate
+        let outpoints = calculate_utxos_required_and_fees(100_000, 20, wallet).unwrap();
+        let indexes: Vec<u32> = outpoints.iter().map(|o| o.output_index).collect();
+        assert_eq!(indexes, vec![1, 0]);
+    }
+}
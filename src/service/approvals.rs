@@ -0,0 +1,139 @@
+use log::{info, warn};
+
+use crate::api::orders::OrdersGetUserMarketOfferOrdersList;
+use crate::approval::{Decision, PendingApproval};
+use crate::clock::now;
+use crate::notify::{Event, EventKind};
+use crate::store::ApprovalRecord;
+
+use super::invoices::order_timeout;
+use super::Service;
+
+impl Service {
+    /**
+     * Parks a new order for the operator when it matches the approval criteria:
+     * 1. Let orders the operator approved go through, retry rejections
+     * 2. Record and notify orders needing approval the first time
+     * 3. Decide by ourselves if the operator didn't before the order times out
+     *
+     * Returns whether the order was held back from being accepted.
+     */
+    pub(super) async fn hold_for_approval(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        let Some(config) = &self.approval else {
+            return Ok(false);
+        };
+
+        let record = self
+            .store
+            .borrow()
+            .orders
+            .get(&order.id)
+            .and_then(|record| record.approval.clone());
+
+        // 1. Let orders the operator approved go through, retry rejections
+        let record = match record {
+            Some(ApprovalRecord {
+                decision: Some(Decision::Approve),
+                ..
+            }) => return Ok(false),
+            Some(ApprovalRecord {
+                decision: Some(Decision::Reject),
+                ..
+            }) => {
+                self.reject_order(&order.id).await?;
+                return Ok(true);
+            }
+            Some(record) => record,
+
+            // 2. Record and notify orders needing approval the first time
+            None => {
+                let size: i64 = order.size.parse()?;
+                let known_buyer = self
+                    .store
+                    .borrow()
+                    .channels
+                    .values()
+                    .any(|channel| channel.buyer == order.account);
+                let Some(reason) = config.reason(size, known_buyer) else {
+                    return Ok(false);
+                };
+
+                let record = ApprovalRecord {
+                    reason,
+                    requested_at: now(),
+                    decision: None,
+                    decided_at: None,
+                };
+                let mut store = self.store.borrow_mut();
+                store.orders.entry(order.id.clone()).or_default().approval = Some(record.clone());
+                store.save()?;
+                drop(store);
+
+                info!(
+                    "Order {} waits for the operator's approval: {}",
+                    order.id, record.reason
+                );
+                self.notify(Event::new(
+                    EventKind::ApprovalNeeded,
+                    Some(&order.id),
+                    format!(
+                        "A {} sats channel to {} waits for approval ({}). Run `approvals approve {}` or `approvals reject {}`",
+                        order.size, order.account, record.reason, order.id, order.id
+                    ),
+                ));
+                record
+            }
+        };
+
+        let deadline = order_timeout(order.timeout.as_deref())
+            .map(|at| at.saturating_sub(config.decide_before()));
+        self.approvals.park(PendingApproval {
+            order_id: order.id.clone(),
+            buyer: order.account.clone(),
+            size: order.size.parse()?,
+            reason: record.reason,
+            requested_at: record.requested_at,
+            deadline,
+        });
+
+        // 3. Decide by ourselves if the operator didn't before the order times out
+        if deadline.is_some_and(|deadline| now() >= deadline) {
+            let decision = config.on_timeout();
+            warn!(
+                "No decision for order {} before it times out, going for {:?}",
+                order.id, decision
+            );
+            self.decide(&order.id, decision).await?;
+        }
+
+        Ok(true)
+    }
+
+    /// Records a decision on a parked order and carries it out.
+    pub(super) async fn decide(
+        &self,
+        order_id: &str,
+        decision: Decision,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.approvals.remove(order_id);
+        {
+            let mut store = self.store.borrow_mut();
+            let record = store.orders.entry(order_id.to_string()).or_default();
+            let Some(approval) = record.approval.as_mut() else {
+                return Err(format!("Order {} isn't waiting for approval", order_id).into());
+            };
+            approval.decision = Some(decision);
+            approval.decided_at = Some(now());
+            store.save()?;
+        }
+
+        info!("Order {}: {:?}", order_id, decision);
+        match decision {
+            Decision::Approve => self.accept(order_id, "approved").await,
+            Decision::Reject => self.reject_order(order_id).await,
+        }
+    }
+}
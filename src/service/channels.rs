@@ -0,0 +1,229 @@
+use lnd_grpc_rust::lnrpc;
+use lnd_grpc_rust::lnrpc::channel_event_update::Channel;
+use log::{debug, error, info, warn};
+
+use crate::api::orders::{OrderStatus, OrdersGetUserMarketOfferOrdersList};
+use crate::node::format_channel_point;
+use crate::store::{ChannelStage, SoldChannel};
+
+use super::Service;
+
+impl Service {
+    /// Starts following the channel opened for an order.
+    pub(super) fn record_sold_channel(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+        channel_point: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = self.store.borrow_mut();
+        store
+            .channels
+            .insert(order.id.clone(), sold_channel(order, channel_point));
+        store.save()
+    }
+
+    /// Changes the stored channel of an order, if any, and saves it.
+    pub(super) fn update_channel(
+        &self,
+        order_id: &str,
+        update: impl FnOnce(&mut SoldChannel),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = self.store.borrow_mut();
+        let Some(channel) = store.channels.get_mut(order_id) else {
+            return Ok(());
+        };
+        update(channel);
+        store.save()
+    }
+
+    /**
+     * Follows the Magma status of an order whose channel was already opened:
+     * WAITING_FOR_ON_CHAIN_CONFIRMATION, VALID_CHANNEL_OPENING,
+     * INVALID_CHANNEL_OPENING and CHANNEL_MONITORING_FINISHED.
+     */
+    pub(super) fn on_sold_channel_status(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = self.store.borrow_mut();
+        let status = format!("{:?}", order.status);
+
+        if !store.channels.contains_key(&order.id) {
+            // Opened before the bot kept track of its channels
+            let Some(channel_point) = order.transaction_id.as_deref() else {
+                debug!("Order {} has no funding transaction yet", order.id);
+                return Ok(());
+            };
+            store
+                .channels
+                .insert(order.id.clone(), sold_channel(order, channel_point));
+        }
+
+        let channel = store.channels.get_mut(&order.id).unwrap();
+        if channel.magma_status.as_deref() == Some(status.as_str()) {
+            return Ok(());
+        }
+
+        info!("Order {} is now {}", order.id, status);
+        channel.magma_status = Some(status);
+
+        match order.status {
+            OrderStatus::INVALID_CHANNEL_OPENING => {
+                error!(
+                    "Magma judged the channel opening of order {} invalid (channel {})",
+                    order.id, channel.channel_point
+                );
+            }
+            OrderStatus::VALID_CHANNEL_OPENING => {
+                info!("Channel opening of order {} validated by Magma", order.id);
+            }
+            OrderStatus::CHANNEL_MONITORING_FINISHED => {
+                info!(
+                    "Magma finished monitoring the channel of order {}",
+                    order.id
+                );
+            }
+            _ => {}
+        }
+
+        store.save()
+    }
+
+    pub(super) async fn on_channel_event(&self, event: lnrpc::ChannelEventUpdate) {
+        if let Some(Channel::ActiveChannel(channel_point)) = &event.channel {
+            self.on_channel_active(&format_channel_point(channel_point))
+                .await;
+            return;
+        }
+
+        let mut store = self.store.borrow_mut();
+
+        match event.channel {
+            Some(Channel::OpenChannel(open)) => {
+                let Some(channel) = store.channel_by_point(&open.channel_point) else {
+                    return;
+                };
+
+                mark_confirmed(channel, open.chan_id);
+            }
+            Some(Channel::ClosedChannel(closed)) => {
+                let Some(channel) = store.channel_by_point(&closed.channel_point) else {
+                    return;
+                };
+
+                if channel.lease_elapsed_height.is_none() {
+                    let blocks_open = channel
+                        .confirmation_height
+                        .map_or(0, |height| closed.close_height.saturating_sub(height));
+                    warn!(
+                        "Channel of order {} closed before its minimum lease ({} of {} blocks)",
+                        channel.order_id, blocks_open, channel.min_block_length
+                    );
+                } else {
+                    info!("Channel of order {} closed", channel.order_id);
+                }
+
+                channel.stage = ChannelStage::Closed;
+            }
+            Some(Channel::InactiveChannel(_)) => {
+                debug!("Channel event: {:?}", event.r#type());
+                return;
+            }
+            _ => return,
+        }
+
+        if let Err(e) = store.save() {
+            error!("Error saving state: {}", e);
+        }
+    }
+
+    /// Records the sold channels whose minimum lease is over.
+    pub(super) async fn check_leases(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let height = self.node.get_info().await?.block_height;
+
+        // Catch up with confirmations missed while the bot was not running
+        let any_pending = self
+            .store
+            .borrow()
+            .channels
+            .values()
+            .any(|channel| channel.stage == ChannelStage::Pending);
+        let open_channels = if any_pending {
+            self.node.list_channels().await?
+        } else {
+            Vec::new()
+        };
+
+        let mut store = self.store.borrow_mut();
+        let mut changed = false;
+
+        for open in open_channels {
+            if let Some(channel) = store.channel_by_point(&open.channel_point) {
+                if channel.stage == ChannelStage::Pending {
+                    mark_confirmed(channel, open.chan_id);
+                    changed = true;
+                }
+            }
+        }
+
+        for channel in store.channels.values_mut() {
+            if channel.stage != ChannelStage::Open || channel.lease_elapsed_height.is_some() {
+                continue;
+            }
+            let Some(lease_end_height) = channel.lease_end_height else {
+                continue;
+            };
+
+            if height >= lease_end_height {
+                info!(
+                    "Minimum lease of {} blocks elapsed for order {} (channel {})",
+                    channel.min_block_length, channel.order_id, channel.channel_point
+                );
+                channel.lease_elapsed_height = Some(height);
+                changed = true;
+            }
+        }
+
+        if changed {
+            store.save()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn mark_confirmed(channel: &mut SoldChannel, chan_id: u64) {
+    // The short channel id starts with the funding block height
+    let height = (chan_id >> 40) as u32;
+    let lease_end_height = height + channel.min_block_length;
+
+    info!(
+        "Channel of order {} confirmed at height {} ({}), leased until height {}",
+        channel.order_id, height, chan_id, lease_end_height
+    );
+
+    channel.chan_id = Some(chan_id);
+    channel.confirmation_height = Some(height);
+    channel.lease_end_height = Some(lease_end_height);
+    channel.stage = ChannelStage::Open;
+}
+
+fn sold_channel(order: &OrdersGetUserMarketOfferOrdersList, channel_point: &str) -> SoldChannel {
+    SoldChannel {
+        order_id: order.id.clone(),
+        buyer: order.account.clone(),
+        size: order.size.parse().unwrap_or_default(),
+        channel_point: channel_point.to_string(),
+        chan_id: None,
+        stage: ChannelStage::Pending,
+        magma_status: None,
+        min_block_length: order.locked_min_block_length as u32,
+        confirmation_height: None,
+        lease_elapsed_height: None,
+        lease_end_height: None,
+        fee_rate_cap: order.locked_fee_rate_cap.map(|cap| cap as u32),
+        base_fee_cap: order.locked_base_fee_cap.map(|cap| cap as i64),
+        broadcast_height: None,
+        bumped_fee_rate: None,
+    }
+}
@@ -0,0 +1,112 @@
+use log::{debug, error, info};
+
+use crate::api::orders::OrdersGetUserMarketOfferOrdersList;
+use crate::chat::{render, ChatConfig};
+use crate::notify::{Event, EventKind};
+
+use super::lifecycle::{action, Action};
+use super::Service;
+
+impl Service {
+    /**
+     * Forwards the chat messages of an order that arrived since the last poll:
+     * 1. Skip the ones already seen, and our own
+     * 2. Notify the others
+     * 3. Remember how many were seen
+     *
+     * Messages of orders the bot meets after they were approved are only
+     * counted, so an old chat isn't forwarded all at once.
+     */
+    pub(super) async fn forward_messages(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let forward = self
+            .chat
+            .as_ref()
+            .and_then(|chat| chat.forward)
+            .unwrap_or(true);
+        let mut messages = order.messages.clone().unwrap_or_default();
+        messages.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+
+        // 1. Skip the ones already seen, and our own
+        let stored = self
+            .store
+            .borrow()
+            .orders
+            .get(&order.id)
+            .and_then(|record| record.messages_seen);
+        let seen = match stored {
+            Some(seen) if seen == messages.len() => return Ok(()),
+            Some(seen) => seen,
+            None if action(&order.status) == Action::Approve => 0,
+            None => messages.len(),
+        };
+
+        // 2. Notify the others
+        if forward && messages.len() > seen {
+            let own_pubkey = self.own_pubkey().await?;
+            for message in messages.iter().skip(seen) {
+                if message.pubkey == own_pubkey {
+                    continue;
+                }
+                info!("Order {} chat: {}", order.id, message.message);
+                self.notify(Event::new(
+                    EventKind::OrderMessage,
+                    Some(&order.id),
+                    format!("{} wrote: {}", message.pubkey, message.message),
+                ));
+            }
+        }
+
+        // 3. Remember how many were seen
+        let mut store = self.store.borrow_mut();
+        store
+            .orders
+            .entry(order.id.clone())
+            .or_default()
+            .messages_seen = Some(messages.len());
+        store.save()
+    }
+
+    /// Pubkey of our node, which signs the messages the bot sends.
+    async fn own_pubkey(&self) -> Result<String, Box<dyn std::error::Error>> {
+        if let Some(pubkey) = self.own_pubkey.borrow().as_ref() {
+            return Ok(pubkey.clone());
+        }
+
+        let pubkey = self.node.get_info().await?.identity_pubkey;
+        self.own_pubkey.replace(Some(pubkey.clone()));
+        Ok(pubkey)
+    }
+
+    /**
+     * Writes an automatic message in the chat of an order, if the chat is
+     * configured and the buyer enabled it. Failing to send it doesn't stop
+     * the order.
+     */
+    pub(super) async fn send_chat(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+        template: fn(&ChatConfig) -> &str,
+        values: &[(&str, &str)],
+    ) {
+        let Some(chat) = &self.chat else {
+            return;
+        };
+        if !order.chat_enabled {
+            debug!("Chat of order {} is disabled", order.id);
+            return;
+        }
+
+        let mut values = values.to_vec();
+        values.push(("order_id", &order.id));
+        values.push(("size", &order.size));
+        let message = render(template(chat), &values);
+
+        match self.api.send_message(&order.id, &message).await {
+            Ok(()) => debug!("Sent to order {} chat: {}", order.id, message),
+            Err(e) => error!("Error writing to order {} chat: {:?}", order.id, e),
+        }
+    }
+}
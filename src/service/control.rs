@@ -0,0 +1,122 @@
+use log::{debug, info};
+use serde_json::{json, Value};
+use tracing::Instrument;
+
+use crate::approval::Decision;
+use crate::control::{Command, Request};
+
+use super::{order_span, Service};
+
+impl Service {
+    /// Answers a request of the control API.
+    pub(super) async fn on_command(&self, command: Command) {
+        debug!("Control request: {:?}", command.request);
+
+        let answer = self
+            .answer(command.request)
+            .await
+            .map_err(|e| e.to_string());
+        // The client may have given up waiting
+        let _ = command.reply.send(answer);
+    }
+
+    async fn answer(&self, request: Request) -> Result<Value, Box<dyn std::error::Error>> {
+        match request {
+            Request::Orders => {
+                self.api.check_budget()?;
+                let orders = self.api.get_actionable_orders().await?;
+                let store = self.store.borrow();
+                let orders: Vec<Value> = orders
+                    .into_iter()
+                    .map(|order| {
+                        json!({
+                            "record": store.orders.get(&order.id),
+                            "reservation": store.reservations.get(&order.id),
+                            "channel": store.channels.get(&order.id),
+                            "order": order,
+                        })
+                    })
+                    .collect();
+                Ok(json!(orders))
+            }
+            Request::Liquidity => {
+                let spendable: i64 = self
+                    .node
+                    .list_unspent()
+                    .await?
+                    .iter()
+                    .map(|utxo| utxo.amount_sat)
+                    .sum();
+                let store = self.store.borrow();
+                let reserved: i64 = store
+                    .reservations
+                    .values()
+                    .map(|reservation| reservation.amount)
+                    .sum();
+                Ok(json!({
+                    "spendable": spendable,
+                    "reserved": reserved,
+                    "reservations": store.reservations,
+                    "offer_adjustments": store.offer_adjustments,
+                }))
+            }
+            Request::Fees { size } => Ok(json!(self.open_cost(size).await?)),
+            Request::Reprocess { order_id } => {
+                self.api.check_budget()?;
+                let order = self.order(&order_id).await?;
+                info!("Reprocessing order {} on the operator's request", order_id);
+                self.handle_order(&order)
+                    .instrument(order_span(&order))
+                    .await?;
+                Ok(json!({ "order_id": order_id, "status": order.status }))
+            }
+            Request::Accept { order_id } => {
+                if self.is_parked(&order_id) {
+                    self.decide(&order_id, Decision::Approve).await?;
+                } else {
+                    self.accept_order(&order_id).await?;
+                }
+                Ok(json!({ "order_id": order_id, "accepted": true }))
+            }
+            Request::Reject { order_id } => {
+                if self.is_parked(&order_id) {
+                    self.decide(&order_id, Decision::Reject).await?;
+                } else {
+                    self.reject_order(&order_id).await?;
+                }
+                Ok(json!({ "order_id": order_id, "rejected": true }))
+            }
+            Request::Cancel { order_id, reason } => {
+                self.cancel_order(&order_id, reason).await?;
+                Ok(json!({ "order_id": order_id, "cancelled": true }))
+            }
+            Request::OpenChannel { order_id } => {
+                self.open_order_channel(&order_id).await?;
+                Ok(json!({ "order_id": order_id, "channel_opened": true }))
+            }
+            Request::SetPaused { paused } => {
+                let mut store = self.store.borrow_mut();
+                store.paused = paused;
+                store.save()?;
+                self.control.set_paused(paused);
+                self.health.set_paused(paused);
+                if paused {
+                    info!("Processing paused by the operator");
+                } else {
+                    info!("Processing resumed by the operator");
+                }
+                Ok(json!({ "paused": paused }))
+            }
+        }
+    }
+
+    /// Whether the order waits for the operator's approval.
+    fn is_parked(&self, order_id: &str) -> bool {
+        self.store
+            .borrow()
+            .orders
+            .get(order_id)
+            .and_then(|record| record.approval.as_ref())
+            .is_some_and(|approval| approval.decision.is_none())
+    }
+}
@@ -0,0 +1,124 @@
+use log::{debug, error, warn};
+
+use crate::lease;
+use crate::store::{ChannelStage, SoldChannel};
+
+use super::Service;
+
+impl Service {
+    /// Re-asserts the fee caps locked by the orders of channels still under lease.
+    pub(super) async fn enforce_fee_caps(&self) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.enforce_fee_caps {
+            return Ok(());
+        }
+
+        let info = self.node.get_info().await?;
+
+        let channels: Vec<SoldChannel> = self
+            .store
+            .borrow()
+            .channels
+            .values()
+            .filter(|channel| channel.stage == ChannelStage::Open)
+            .filter(|channel| channel.fee_rate_cap.is_some() || channel.base_fee_cap.is_some())
+            .filter(|channel| lease::is_under_lease(channel, info.block_height))
+            .cloned()
+            .collect();
+
+        for channel in channels {
+            if let Err(e) = self.enforce_fee_cap(&channel, &info.identity_pubkey).await {
+                error!(
+                    "Error enforcing fee cap on channel of order {}: {:?}",
+                    channel.order_id, e
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sets the caps right away when a sold channel becomes active.
+    pub(super) async fn on_channel_active(&self, channel_point: &str) {
+        if !self.enforce_fee_caps {
+            return;
+        }
+
+        let channel = self
+            .store
+            .borrow_mut()
+            .channel_by_point(channel_point)
+            .map(|channel| channel.clone());
+
+        let Some(channel) = channel else {
+            return;
+        };
+        debug!("Channel of order {} is active", channel.order_id);
+
+        let result = async {
+            let info = self.node.get_info().await?;
+            if !lease::is_under_lease(&channel, info.block_height) {
+                return Ok(());
+            }
+            self.enforce_fee_cap(&channel, &info.identity_pubkey).await
+        }
+        .await;
+
+        if let Err(e) = result {
+            error!(
+                "Error enforcing fee cap on channel of order {}: {:?}",
+                channel.order_id, e
+            );
+        }
+    }
+
+    /// Lowers our outbound policy of a sold channel to the order's caps, if above.
+    async fn enforce_fee_cap(
+        &self,
+        channel: &SoldChannel,
+        our_pubkey: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let chan_id = channel.chan_id.ok_or("Channel not confirmed yet")?;
+        let edge = self.node.get_chan_info(chan_id).await?;
+
+        let policy = if edge.node1_pub == our_pubkey {
+            edge.node1_policy
+        } else {
+            edge.node2_policy
+        }
+        .ok_or("Our channel policy is not known yet")?;
+
+        let fee_rate_ppm = channel
+            .fee_rate_cap
+            .map_or(policy.fee_rate_milli_msat, |cap| {
+                policy.fee_rate_milli_msat.min(cap as i64)
+            });
+        let base_fee_msat = channel.base_fee_cap.map_or(policy.fee_base_msat, |cap| {
+            policy
+                .fee_base_msat
+                .min(self.base_fee_cap_unit.to_msat(cap))
+        });
+
+        if fee_rate_ppm == policy.fee_rate_milli_msat && base_fee_msat == policy.fee_base_msat {
+            debug!("Fees of order {} channel within caps", channel.order_id);
+            return Ok(());
+        }
+
+        warn!(
+            "Fees of order {} channel above cap ({} msat + {} ppm), lowering to {} msat + {} ppm",
+            channel.order_id,
+            policy.fee_base_msat,
+            policy.fee_rate_milli_msat,
+            base_fee_msat,
+            fee_rate_ppm
+        );
+
+        self.node
+            .update_channel_policy(
+                &channel.channel_point,
+                base_fee_msat,
+                fee_rate_ppm as u32,
+                policy.time_lock_delta,
+            )
+            .await
+    }
+}
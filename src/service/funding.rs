@@ -0,0 +1,109 @@
+use log::{debug, info, warn};
+
+use crate::mempool;
+use crate::store::ChannelStage;
+
+use super::Service;
+
+impl Service {
+    /**
+     * Watches the funding transactions of sold channels still pending:
+     * 1. Note the height at which each one was first seen unconfirmed
+     * 2. Once it waited `after_blocks`, bump it to the fastest fee, up to the
+     *    configured ceiling, by spending our change output (CPFP)
+     */
+    pub(super) async fn bump_stuck_fundings(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(config) = &self.fee_bumping else {
+            return Ok(());
+        };
+
+        let any_pending = self
+            .store
+            .borrow()
+            .channels
+            .values()
+            .any(|channel| channel.stage == ChannelStage::Pending);
+        if !any_pending {
+            return Ok(());
+        }
+
+        let height = self.node.get_info().await?.block_height;
+        let unconfirmed = self.node.list_unconfirmed_transactions().await?;
+        let fastest_fee = mempool::get_fastest_fee().await? as u64;
+        let target_fee_rate = config.target_fee_rate(fastest_fee);
+
+        let pending: Vec<_> = self
+            .store
+            .borrow()
+            .channels
+            .values()
+            .filter(|channel| channel.stage == ChannelStage::Pending)
+            .cloned()
+            .collect();
+
+        for channel in pending {
+            let Some((txid, funding_index)) = channel.channel_point.split_once(':') else {
+                continue;
+            };
+            let Some(tx) = unconfirmed.iter().find(|tx| tx.tx_hash == txid) else {
+                // Confirmed, or not known to the wallet
+                continue;
+            };
+
+            // 1. Note the height at which it was first seen unconfirmed
+            let Some(broadcast_height) = channel.broadcast_height else {
+                self.update_channel(&channel.order_id, |stored| {
+                    stored.broadcast_height = Some(height)
+                })?;
+                continue;
+            };
+
+            // 2. Bump it once it waited long enough
+            if !config.is_due(broadcast_height, height) {
+                continue;
+            }
+            if channel
+                .bumped_fee_rate
+                .is_some_and(|rate| rate >= target_fee_rate)
+            {
+                debug!(
+                    "Funding of order {} already bumped to {} sat/vB",
+                    channel.order_id, target_fee_rate
+                );
+                continue;
+            }
+
+            let change = tx.output_details.iter().find(|output| {
+                output.is_our_address && output.output_index.to_string() != funding_index
+            });
+            let Some(change) = change else {
+                warn!(
+                    "Funding of order {} unconfirmed for {} blocks, but it has no change output to bump",
+                    channel.order_id,
+                    height - broadcast_height
+                );
+                continue;
+            };
+
+            let outpoint = format!("{}:{}", txid, change.output_index);
+            info!(
+                "Funding of order {} unconfirmed for {} blocks, bumping it to {} sat/vB via {}",
+                channel.order_id,
+                height - broadcast_height,
+                target_fee_rate,
+                outpoint
+            );
+            let status = self
+                .node
+                .bump_fee(&outpoint, target_fee_rate, config.budget.unwrap_or(0))
+                .await?;
+            debug!("Bump fee: {}", status);
+
+            self.update_channel(&channel.order_id, |stored| {
+                stored.bumped_fee_rate = Some(target_fee_rate)
+            })?;
+        }
+
+        Ok(())
+    }
+}
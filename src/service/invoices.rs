@@ -0,0 +1,235 @@
+use chrono::DateTime;
+use lnd_grpc_rust::lnrpc;
+use log::{debug, info, warn};
+
+use crate::api::orders::{OrderPaymentStatus, OrdersGetUserMarketOfferOrdersList};
+use crate::clock::now;
+
+use super::Service;
+
+/// Invoice expiry when the order has no timeout. Default is 2 days
+const DEFAULT_INVOICE_EXPIRY: i64 = 172800;
+
+/// Shortest invoice expiry we hand out, so the buyer still has time to pay
+const MIN_INVOICE_EXPIRY: i64 = 600;
+
+impl Service {
+    /**
+     * Creates the invoice the buyer pays for an order:
+     * - The memo carries the order id, to tell invoices apart in LND
+     * - It expires when the order times out on Magma
+     * - Its payment hash is saved, to look up its status per order later
+     */
+    pub(super) async fn create_order_invoice(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+    ) -> Result<lnrpc::AddInvoiceResponse, Box<dyn std::error::Error>> {
+        let order_cost = order
+            .seller_invoice_amount
+            .as_ref()
+            .ok_or("Order has no seller invoice amount")?
+            .parse::<i64>()?;
+        let expiry = invoice_expiry(order.timeout.as_deref());
+        let memo = format!("Amboss Magma order {}", order.id);
+
+        let invoice = self.node.create_invoice(order_cost, expiry, &memo).await?;
+        debug!(
+            "Invoice created for order {}, expires in {}s: {}",
+            order.id, expiry, invoice.payment_request
+        );
+
+        let mut store = self.store.borrow_mut();
+        let record = store.orders.entry(order.id.clone()).or_default();
+        record.payment_hash = Some(hex::encode(&invoice.r_hash));
+        record.invoice_expires_at = Some(now() + expiry as u64);
+        store.save()?;
+
+        Ok(invoice)
+    }
+
+    /**
+     * Hands a fresh invoice to an order whose previous one expired or timed
+     * out before the buyer paid:
+     * 1. Make sure our own invoice wasn't paid in the meantime, and did expire
+     * 2. Create a new invoice
+     * 3. Accept the order again with it
+     *
+     * Magma keeps reporting the failure until it sees the new invoice, which
+     * isn't renewed again before it expires too.
+     */
+    pub(super) async fn renew_order_invoice(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // 1. Make sure our own invoice wasn't paid in the meantime, and did expire
+        let expires_at = self
+            .store
+            .borrow()
+            .orders
+            .get(&order.id)
+            .and_then(|record| record.invoice_expires_at);
+        let expired = expires_at.is_none_or(|expires_at| expires_at <= now());
+        match self.order_invoice_state(&order.id).await {
+            Ok(Some(
+                state @ (lnrpc::invoice::InvoiceState::Settled
+                | lnrpc::invoice::InvoiceState::Accepted),
+            )) => {
+                warn!(
+                    "Invoice of order {} is {:?} in LND, not replacing it",
+                    order.id, state
+                );
+                return Ok(());
+            }
+            Ok(Some(lnrpc::invoice::InvoiceState::Canceled)) => {}
+            Ok(_) if !expired => {
+                debug!(
+                    "Invoice of order {} is still valid, waiting for Magma to see it",
+                    order.id
+                );
+                return Ok(());
+            }
+            Ok(_) => {}
+            Err(e) => debug!("Error looking up invoice of order {}: {}", order.id, e),
+        }
+
+        warn!(
+            "Invoice of order {} failed ({:?}), creating a new one",
+            order.id, order.payment_status
+        );
+
+        // 2. Create a new invoice
+        let invoice = self.create_order_invoice(order).await?;
+
+        // 3. Accept the order again with it
+        if let Err(e) = self
+            .api
+            .accept_order(order.id.as_str(), invoice.payment_request.as_str())
+            .await
+        {
+            warn!("Magma refused the new invoice of order {}: {}", order.id, e);
+            return Err(e);
+        }
+        info!("Sent a new invoice for order {}", order.id);
+
+        let mut store = self.store.borrow_mut();
+        store
+            .orders
+            .entry(order.id.clone())
+            .or_default()
+            .invoice_renewals += 1;
+        store.save()?;
+        drop(store);
+
+        self.pending_invoices
+            .borrow_mut()
+            .insert(invoice.r_hash, order.clone());
+
+        Ok(())
+    }
+
+    /// State in LND of the invoice created for an order, if we made one.
+    pub(super) async fn order_invoice_state(
+        &self,
+        order_id: &str,
+    ) -> Result<Option<lnrpc::invoice::InvoiceState>, Box<dyn std::error::Error>> {
+        let payment_hash = self
+            .store
+            .borrow()
+            .orders
+            .get(order_id)
+            .and_then(|record| record.payment_hash.clone());
+        let Some(payment_hash) = payment_hash else {
+            return Ok(None);
+        };
+
+        let invoice = self.node.lookup_invoice(hex::decode(payment_hash)?).await?;
+        let state = invoice.state();
+        debug!("Invoice of order {} is {:?}", order_id, state);
+
+        Ok(Some(state))
+    }
+}
+
+/// Seconds until the order times out, with defaults for orders without one.
+fn invoice_expiry(timeout: Option<&str>) -> i64 {
+    if timeout.is_none() {
+        return DEFAULT_INVOICE_EXPIRY;
+    }
+
+    match order_timeout(timeout) {
+        Some(secs) => (secs as i64 - now() as i64).max(MIN_INVOICE_EXPIRY),
+        None => DEFAULT_INVOICE_EXPIRY,
+    }
+}
+
+/// Unix time at which the order times out, which Magma returns as a date or
+/// as milliseconds since the epoch.
+pub(super) fn order_timeout(timeout: Option<&str>) -> Option<u64> {
+    let timeout = timeout?;
+
+    let timeout_secs = DateTime::parse_from_rfc3339(timeout)
+        .map(|date| date.timestamp())
+        .ok()
+        .or_else(|| timeout.parse::<i64>().ok().map(|millis| millis / 1000));
+    if timeout_secs.is_none() {
+        debug!("Unknown order timeout format: {}", timeout);
+    }
+
+    timeout_secs.map(|secs| secs.max(0) as u64)
+}
+
+/// Whether Magma reports the order's invoice as expired or timed out.
+pub(super) fn invoice_failed(order: &OrdersGetUserMarketOfferOrdersList) -> bool {
+    matches!(
+        order.payment_status,
+        Some(OrderPaymentStatus::SELLER_INVOICE_EXPIRED | OrderPaymentStatus::HODL_INVOICE_TIMEOUT)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_timeout_reads_dates_and_milliseconds() {
+        assert_eq!(
+            order_timeout(Some("2024-01-01T00:00:00.000Z")),
+            Some(1_704_067_200)
+        );
+        assert_eq!(
+            order_timeout(Some("2024-01-01T02:00:00+02:00")),
+            Some(1_704_067_200)
+        );
+        assert_eq!(order_timeout(Some("1704067200000")), Some(1_704_067_200));
+        assert_eq!(order_timeout(Some("tomorrow")), None);
+        assert_eq!(order_timeout(None), None);
+    }
+
+    #[test]
+    fn invoice_expires_with_the_order() {
+        let in_an_hour = now() + 3600;
+        let date = DateTime::from_timestamp(in_an_hour as i64, 0)
+            .unwrap()
+            .to_rfc3339();
+        let millis = (in_an_hour * 1000).to_string();
+
+        // Allow for the clock moving between the two calls
+        assert!((3595..=3600).contains(&invoice_expiry(Some(&date))));
+        assert!((3595..=3600).contains(&invoice_expiry(Some(&millis))));
+    }
+
+    #[test]
+    fn invoice_expiry_defaults() {
+        assert_eq!(invoice_expiry(None), DEFAULT_INVOICE_EXPIRY);
+        assert_eq!(invoice_expiry(Some("tomorrow")), DEFAULT_INVOICE_EXPIRY);
+    }
+
+    #[test]
+    fn invoice_expiry_leaves_time_to_pay() {
+        assert_eq!(
+            invoice_expiry(Some("2024-01-01T00:00:00Z")),
+            MIN_INVOICE_EXPIRY
+        );
+        assert_eq!(invoice_expiry(Some("0")), MIN_INVOICE_EXPIRY);
+    }
+}
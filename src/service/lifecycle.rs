@@ -0,0 +1,272 @@
+use log::{debug, error, info, warn};
+use tokio::time::{Duration, Instant};
+
+use crate::api::orders::{OrderStatus, OrdersGetUserMarketOfferOrdersList};
+use crate::clock::now;
+use crate::notify::{Event, EventKind};
+use crate::store::StatusChange;
+
+use super::invoices::invoice_failed;
+use super::Service;
+
+/// How often orders that left the actionable statuses are looked up.
+const TRACKED_ORDERS_INTERVAL: Duration = Duration::from_secs(600);
+
+/// What the bot does for an order, given its Magma status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Check the buyer, reserve funds, invoice and accept the order
+    Approve,
+    /// Wait for the buyer to pay, renewing the invoice if it failed
+    AwaitPayment,
+    /// Open the channel and confirm it to Magma
+    OpenChannel,
+    /// Follow the sold channel until Magma stops monitoring it
+    FollowChannel,
+    /// Give back the funds reserved for an order that won't happen
+    ReleaseFunds,
+    /// Nothing left to do
+    Ignore,
+}
+
+/// The bot's action for each order status.
+pub fn action(status: &OrderStatus) -> Action {
+    match status {
+        OrderStatus::WAITING_FOR_SELLER_APPROVAL => Action::Approve,
+        OrderStatus::WAITING_FOR_BUYER_PAYMENT => Action::AwaitPayment,
+        OrderStatus::WAITING_FOR_CHANNEL_OPEN => Action::OpenChannel,
+
+        OrderStatus::SELLER_OPENED_CHANNEL
+        | OrderStatus::SELLER_SENT_TRANSACTION
+        | OrderStatus::ON_CHAIN_CONFIRMATION
+        | OrderStatus::WAITING_FOR_ON_CHAIN_CONFIRMATION
+        | OrderStatus::VALID_CHANNEL_OPENING
+        | OrderStatus::INVALID_CHANNEL_OPENING
+        | OrderStatus::CHANNEL_MONITORING_FINISHED => Action::FollowChannel,
+
+        OrderStatus::BUYER_FAILED_TO_PAY
+        | OrderStatus::BUYER_REJECTED
+        | OrderStatus::SELLER_REJECTED
+        | OrderStatus::SELLER_FAILED_TO_REACT
+        | OrderStatus::SELLER_FAILED_TO_OPEN_CHANNEL
+        | OrderStatus::SELLER_FAILED_TO_SEND_SWAP
+        | OrderStatus::ADMIN_CLOSED => Action::ReleaseFunds,
+
+        OrderStatus::Other(_) => Action::Ignore,
+    }
+}
+
+/// Whether Magma is done moving the order.
+fn is_final(status: &OrderStatus) -> bool {
+    matches!(action(status), Action::ReleaseFunds | Action::Ignore)
+        || matches!(
+            status,
+            OrderStatus::INVALID_CHANNEL_OPENING | OrderStatus::CHANNEL_MONITORING_FINISHED
+        )
+}
+
+impl Service {
+    /**
+     * Fetches the orders we recorded in a status that isn't final, but that
+     * are no longer among the actionable ones: their new status tells how
+     * they ended. It takes a query per order, so it's only done every
+     * `TRACKED_ORDERS_INTERVAL`.
+     */
+    pub(super) async fn get_tracked_orders(
+        &self,
+        actionable: &[OrdersGetUserMarketOfferOrdersList],
+    ) -> Vec<OrdersGetUserMarketOfferOrdersList> {
+        if let Some(last) = *self.last_tracked.borrow() {
+            if last.elapsed() < TRACKED_ORDERS_INTERVAL {
+                return Vec::new();
+            }
+        }
+        if let Err(e) = self.api.check_budget() {
+            info!("Not looking up tracked orders: {}", e);
+            return Vec::new();
+        }
+        *self.last_tracked.borrow_mut() = Some(Instant::now());
+
+        let tracked: Vec<String> = self
+            .store
+            .borrow()
+            .orders
+            .iter()
+            .filter(|(_, record)| {
+                record
+                    .status
+                    .as_ref()
+                    .and_then(|status| serde_json::from_value(status.as_str().into()).ok())
+                    .is_some_and(|status| !is_final(&status))
+            })
+            .map(|(order_id, _)| order_id.clone())
+            .filter(|order_id| !actionable.iter().any(|order| &order.id == order_id))
+            .collect();
+
+        let mut orders = Vec::new();
+        for order_id in tracked {
+            if let Err(e) = self.api.check_budget() {
+                info!("Looking up the other tracked orders later: {}", e);
+                break;
+            }
+            match self.api.get_order(&order_id).await {
+                Ok(Some(order)) => orders.push(order),
+                Ok(None) => debug!("Order {} not found", order_id),
+                Err(e) => error!("Error fetching order {}: {:?}", order_id, e),
+            }
+        }
+        orders
+    }
+
+    /**
+     * Moves an order along its life:
+     * 1. Record the status if it changed, and react to the transition,
+     *    then forward new chat messages
+     * 2. Run the action of the current status
+     */
+    pub(super) async fn handle_order(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // 1. Record the status if it changed, react to the transition, and
+        //    forward new chat messages
+        if let Some(previous) = self.record_status(order)? {
+            self.on_transition(order, previous.as_deref());
+        }
+        if let Err(e) = self.forward_messages(order).await {
+            error!("Error forwarding messages of order {}: {:?}", order.id, e);
+        }
+        if action(&order.status) != Action::Approve {
+            self.approvals.remove(&order.id);
+        }
+
+        // 2. Run the action of the current status
+        match action(&order.status) {
+            Action::Approve | Action::AwaitPayment if invoice_failed(order) => {
+                self.renew_order_invoice(order).await
+            }
+            Action::Approve if self.hold_for_approval(order).await? => Ok(()),
+            Action::Approve => {
+                info!("Approving order: {}", order.id);
+                self.process_new_order(order, "auto").await
+            }
+            Action::AwaitPayment => {
+                debug!("Waiting for the buyer to pay order {}", order.id);
+                Ok(())
+            }
+            Action::OpenChannel => {
+                info!("Opening channel for order: {}", order.id);
+                self.open_channel(order).await
+            }
+            Action::FollowChannel => self.on_sold_channel_status(order),
+            Action::ReleaseFunds => {
+                let reason = format!("{:?}", order.status);
+                self.release_reservation(&order.id, &reason).await;
+                Ok(())
+            }
+            Action::Ignore => {
+                debug!("Skipping order: {} ({:?})", order.id, order.status);
+                Ok(())
+            }
+        }
+    }
+
+    /**
+     * Saves the order's status when it changed since the last poll.
+     *
+     * Returns the previous status if the bot already knew the order, `None`
+     * when nothing changed. Orders seen for the first time are only recorded,
+     * so history from before the bot ran doesn't trigger alerts.
+     */
+    fn record_status(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+    ) -> Result<Option<Option<String>>, Box<dyn std::error::Error>> {
+        let status = format!("{:?}", order.status);
+        let mut store = self.store.borrow_mut();
+
+        let known = store.orders.contains_key(&order.id);
+        let record = store.orders.entry(order.id.clone()).or_default();
+        if record.status.as_deref() == Some(status.as_str()) {
+            return Ok(None);
+        }
+
+        let previous = record.status.replace(status.clone());
+        self.metrics
+            .orders_seen
+            .with_label_values(&[status.as_str()])
+            .inc();
+        record.history.push(StatusChange {
+            status: status.clone(),
+            at: now(),
+        });
+        info!(
+            "Order {}: {} -> {}",
+            order.id,
+            previous.as_deref().unwrap_or("new"),
+            status
+        );
+        store.save()?;
+
+        Ok(known.then_some(previous))
+    }
+
+    /// Reacts once to an order entering a new status.
+    fn on_transition(&self, order: &OrdersGetUserMarketOfferOrdersList, from: Option<&str>) {
+        match order.status {
+            OrderStatus::SELLER_FAILED_TO_REACT
+            | OrderStatus::SELLER_FAILED_TO_OPEN_CHANNEL
+            | OrderStatus::SELLER_FAILED_TO_SEND_SWAP => {
+                error!(
+                    "Order {} failed on our side: {:?} (was {})",
+                    order.id,
+                    order.status,
+                    from.unwrap_or("unknown")
+                );
+                self.notify(Event::new(
+                    EventKind::OrderFailed,
+                    Some(&order.id),
+                    format!("Failed on our side: {:?}", order.status),
+                ));
+            }
+            OrderStatus::BUYER_FAILED_TO_PAY | OrderStatus::BUYER_REJECTED => {
+                warn!(
+                    "Order {} dropped by the buyer: {:?}",
+                    order.id, order.status
+                );
+            }
+            OrderStatus::ADMIN_CLOSED => {
+                warn!("Order {} closed by Magma admins", order.id);
+            }
+            OrderStatus::VALID_CHANNEL_OPENING => self.record_revenue(order),
+            _ => {}
+        }
+    }
+
+    /// Books the order's price once Magma validated its channel.
+    fn record_revenue(&self, order: &OrdersGetUserMarketOfferOrdersList) {
+        let revenue: i64 = order
+            .seller_invoice_amount
+            .as_deref()
+            .and_then(|amount| amount.parse().ok())
+            .unwrap_or_default();
+
+        let mut store = self.store.borrow_mut();
+        if let Some(record) = store.orders.get_mut(&order.id) {
+            record.revenue = Some(revenue);
+        }
+        let total: i64 = store
+            .orders
+            .values()
+            .filter_map(|record| record.revenue)
+            .sum();
+        info!(
+            "Earned {} sats from order {} ({} sats in total)",
+            revenue, order.id, total
+        );
+
+        if let Err(e) = store.save() {
+            error!("Error saving state: {}", e);
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use log::{debug, info, warn};
+use tokio::time::{Duration, Instant};
+
+use crate::api::market_offers::OfferStatus;
+use crate::api::orders::{OrderStatus, OrdersGetUserMarketOfferOrdersList};
+use crate::api::OfferChanges;
+use crate::mempool;
+
+use super::Service;
+
+impl Service {
+    /// Pushes new offer prices when on-chain fees or our liquidity moved enough.
+    pub(super) async fn reprice_offer(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(pricing) = &self.pricing else {
+            return Ok(());
+        };
+
+        let interval = Duration::from_secs(pricing.interval.unwrap_or(600));
+        if let Some(last) = *self.last_priced.borrow() {
+            if last.elapsed() < interval {
+                return Ok(());
+            }
+        }
+        // Repricing can wait, keep the query budget for orders
+        if let Err(e) = self.api.check_budget() {
+            info!("Not repricing offers: {}", e);
+            return Ok(());
+        }
+        *self.last_priced.borrow_mut() = Some(Instant::now());
+
+        let offers = self.api.get_sell_offers().await?;
+        let offer = match &pricing.offer_id {
+            Some(id) => offers.iter().find(|offer| &offer.id == id),
+            None => offers.first(),
+        }
+        .ok_or("No offer to price")?;
+
+        let sat_per_vbyte = mempool::get_fastest_fee().await?;
+        let spendable: i64 = self
+            .node
+            .list_unspent()
+            .await?
+            .iter()
+            .map(|utxo| utxo.amount_sat)
+            .sum();
+
+        let changes = pricing.reprice(offer, sat_per_vbyte, spendable);
+        if changes.is_empty() {
+            debug!(
+                "Offer {} prices still fine at {} sat/vB, {} sats spendable",
+                offer.id, sat_per_vbyte, spendable
+            );
+            return Ok(());
+        }
+
+        info!(
+            "Repricing offer {} at {} sat/vB, {} sats spendable: {:?}",
+            offer.id, sat_per_vbyte, spendable, changes
+        );
+        self.api.adjust_offer(offer, &changes).await
+    }
+
+    /**
+     * Keeps our offers within what the wallet can actually fund:
+     * - Disables an offer when the available balance is below its `min_size`
+     * - Shrinks its `max_size` down to the available balance
+     * - Undoes both once the balance is back
+     *
+     * The available balance is what's spendable minus the size of orders
+     * already accepted but not opened yet, unless their funds are reserved.
+     */
+    pub(super) async fn guard_offer_liquidity(
+        &self,
+        orders: &[OrdersGetUserMarketOfferOrdersList],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.guard_liquidity {
+            return Ok(());
+        }
+        // Offers are checked again on the next poll, keep the budget for orders
+        if let Err(e) = self.api.check_budget() {
+            info!("Not checking offer liquidity: {}", e);
+            return Ok(());
+        }
+
+        // Reserved funds are already hidden from the wallet's UTXOs
+        let committed: i64 = {
+            let store = self.store.borrow();
+            orders
+                .iter()
+                .filter(|order| {
+                    matches!(
+                        order.status,
+                        OrderStatus::WAITING_FOR_BUYER_PAYMENT
+                            | OrderStatus::WAITING_FOR_CHANNEL_OPEN
+                    )
+                })
+                .filter(|order| !store.reservations.contains_key(&order.id))
+                .map(|order| order.size.parse::<i64>().unwrap_or_default())
+                .sum()
+        };
+
+        let spendable: i64 = self
+            .node
+            .list_unspent()
+            .await?
+            .iter()
+            .map(|utxo| utxo.amount_sat)
+            .sum();
+        let available = (spendable - committed).max(0) as f64;
+        debug!(
+            "Available for new orders: {} sats ({} spendable, {} committed)",
+            available, spendable, committed
+        );
+
+        for offer in self.api.get_sell_offers().await? {
+            let min_size: f64 = offer.min_size.parse()?;
+            let max_size: f64 = offer.max_size.parse()?;
+            let adjustment = self
+                .store
+                .borrow()
+                .offer_adjustments
+                .get(&offer.id)
+                .cloned()
+                .unwrap_or_default();
+            let mut updated = adjustment.clone();
+
+            match offer.status {
+                OfferStatus::ENABLED if available < min_size => {
+                    warn!(
+                        "Disabling offer {}: {} sats available, min size is {}",
+                        offer.id, available, min_size
+                    );
+                    self.api.toggle_offer(&offer.id).await?;
+                    updated.disabled = true;
+                }
+                OfferStatus::DISABLED if adjustment.disabled && available >= min_size => {
+                    info!(
+                        "Enabling offer {} again: {} sats available",
+                        offer.id, available
+                    );
+                    self.api.toggle_offer(&offer.id).await?;
+                    updated.disabled = false;
+                }
+                _ => {}
+            }
+
+            let original_max_size = adjustment.original_max_size.unwrap_or(max_size);
+            let target_max_size = available.min(original_max_size).max(min_size).floor();
+
+            if target_max_size != max_size {
+                info!(
+                    "Changing max size of offer {} from {} to {} sats",
+                    offer.id, max_size, target_max_size
+                );
+                let changes = OfferChanges {
+                    max_size: Some(target_max_size),
+                    ..Default::default()
+                };
+                self.api.adjust_offer(&offer, &changes).await?;
+            }
+            updated.original_max_size =
+                Some(original_max_size).filter(|original| target_max_size < *original);
+
+            if updated.disabled != adjustment.disabled
+                || updated.original_max_size != adjustment.original_max_size
+            {
+                let mut store = self.store.borrow_mut();
+                if updated.disabled || updated.original_max_size.is_some() {
+                    store.offer_adjustments.insert(offer.id.clone(), updated);
+                } else {
+                    store.offer_adjustments.remove(&offer.id);
+                }
+                store.save()?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use log::info;
+
+use crate::api::cancel_order::OrderCancellationReason;
+use crate::api::orders::OrdersGetUserMarketOfferOrdersList;
+use crate::mempool;
+use crate::node::LNNode;
+use crate::notify::{Event, EventKind};
+
+use super::lifecycle::{action, Action};
+use super::{calc_fee, calculate_utxos_required_and_fees, Service};
+
+/// What opening a channel would cost right now.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct OpenCost {
+    /// Fastest fee rate on mempool.space, in sat/vB
+    pub sat_per_vbyte: u8,
+    /// UTXOs the funding transaction would spend
+    pub inputs: usize,
+    /// Fee of the funding transaction, in sats
+    pub fee: f64,
+    /// Confirmed wallet balance not reserved for an order, in sats
+    pub spendable: i64,
+}
+
+impl Service {
+    /// Fetches an order on our offers, failing if Magma doesn't know it.
+    pub async fn order(
+        &self,
+        order_id: &str,
+    ) -> Result<OrdersGetUserMarketOfferOrdersList, Box<dyn std::error::Error>> {
+        self.api
+            .get_order(order_id)
+            .await?
+            .ok_or_else(|| format!("Order {} not found", order_id).into())
+    }
+
+    /// Accepts an order waiting for us, as the loop would.
+    pub async fn accept_order(&self, order_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.accept(order_id, "operator").await
+    }
+
+    /// Accepts an order waiting for us, labelling it with `reason` in the metrics.
+    pub(super) async fn accept(
+        &self,
+        order_id: &str,
+        reason: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let order = self.order(order_id).await?;
+        expect_action(&order, Action::Approve)?;
+
+        self.process_new_order(&order, reason).await
+    }
+
+    /// Rejects an order waiting for us, giving back the funds reserved for it.
+    pub async fn reject_order(&self, order_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let order = self.order(order_id).await?;
+        expect_action(&order, Action::Approve)?;
+
+        info!("Rejecting order {} on the operator's request", order.id);
+        self.api.reject_order(&order.id).await?;
+        self.metrics
+            .orders_rejected
+            .with_label_values(&["operator"])
+            .inc();
+        self.notify(Event::new(
+            EventKind::OrderRejected,
+            Some(&order.id),
+            "Rejected by the operator",
+        ));
+        self.release_reservation(&order.id, "order rejected").await;
+
+        Ok(())
+    }
+
+    /// Cancels an accepted order, giving back the funds reserved for it.
+    pub async fn cancel_order(
+        &self,
+        order_id: &str,
+        reason: OrderCancellationReason,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let order = self.order(order_id).await?;
+        if !matches!(
+            action(&order.status),
+            Action::AwaitPayment | Action::OpenChannel
+        ) {
+            Err(format!(
+                "Order {} is {:?}, only accepted or paid orders can be cancelled",
+                order.id, order.status
+            ))?;
+        }
+
+        let reason_label = format!("{:?}", reason);
+        info!(
+            "Cancelling order {} on the operator's request: {}",
+            order.id, reason_label
+        );
+        self.api.cancel_order(&order.id, reason).await?;
+        self.metrics
+            .orders_cancelled
+            .with_label_values(&[reason_label.as_str()])
+            .inc();
+        self.notify(Event::new(
+            EventKind::OrderCancelled,
+            Some(&order.id),
+            format!("Cancelled by the operator: {}", reason_label),
+        ));
+        self.release_reservation(&order.id, "order cancelled").await;
+
+        Ok(())
+    }
+
+    /// Opens the channel of a paid order, or confirms it if it's already open.
+    pub async fn open_order_channel(
+        &self,
+        order_id: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let order = self.order(order_id).await?;
+        expect_action(&order, Action::OpenChannel)?;
+
+        self.open_channel(&order).await
+    }
+
+    /// Estimates the funding transaction of a channel with the service's node.
+    pub async fn open_cost(
+        &self,
+        channel_size: Option<i64>,
+    ) -> Result<OpenCost, Box<dyn std::error::Error>> {
+        open_cost(&self.node, channel_size).await
+    }
+}
+
+/**
+ * Estimates the funding transaction of a channel at the current fee rate.
+ * Without a size, assumes a single input.
+ */
+pub async fn open_cost(
+    node: &LNNode,
+    channel_size: Option<i64>,
+) -> Result<OpenCost, Box<dyn std::error::Error>> {
+    let sat_per_vbyte = mempool::get_fastest_fee().await?;
+    let utxos = node.list_unspent().await?;
+    let spendable = utxos.iter().map(|utxo| utxo.amount_sat).sum();
+
+    let inputs = match channel_size {
+        Some(size) => calculate_utxos_required_and_fees(size, sat_per_vbyte, utxos)?.len(),
+        None => 1,
+    };
+
+    Ok(OpenCost {
+        sat_per_vbyte,
+        inputs,
+        fee: calc_fee(inputs, sat_per_vbyte),
+        spendable,
+    })
+}
+
+/// Fails unless the order's status calls for the given action.
+fn expect_action(
+    order: &OrdersGetUserMarketOfferOrdersList,
+    expected: Action,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if action(&order.status) != expected {
+        Err(format!(
+            "Order {} is {:?}, which doesn't call for {:?}",
+            order.id, order.status, expected
+        ))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use lnd_grpc_rust::lnrpc;
+use log::{debug, info, warn};
+
+use crate::api::orders::OrdersGetUserMarketOfferOrdersList;
+use crate::clock::now;
+use crate::node::format_outpoint;
+use crate::store::Reservation;
+
+use super::Service;
+
+/// How long UTXOs stay reserved: longer than the invoice expiry, leaving time
+/// to open the channel once it's paid.
+const RESERVATION_SECONDS: u64 = 3 * 24 * 60 * 60;
+
+impl Service {
+    /**
+     * Leases the UTXOs picked to open the order's channel in LND, so no other
+     * order or wallet operation can spend them before the channel is opened.
+     */
+    pub(super) async fn reserve_funds(
+        &self,
+        order: &OrdersGetUserMarketOfferOrdersList,
+        outpoints: &[lnrpc::OutPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let channel_size: i64 = order.size.parse()?;
+        let outpoints: Vec<String> = outpoints.iter().map(format_outpoint).collect();
+
+        for (leased, outpoint) in outpoints.iter().enumerate() {
+            if let Err(e) = self.node.lease_output(outpoint, RESERVATION_SECONDS).await {
+                self.release_outputs(&outpoints[..leased]).await;
+                return Err(e);
+            }
+        }
+
+        info!(
+            "Reserved {} UTXOs for order {}: {:?}",
+            outpoints.len(),
+            order.id,
+            outpoints
+        );
+
+        let mut store = self.store.borrow_mut();
+        store.reservations.insert(
+            order.id.clone(),
+            Reservation {
+                outpoints,
+                amount: channel_size,
+                expires_at: now() + RESERVATION_SECONDS,
+            },
+        );
+        store.save()
+    }
+
+    /// Gives the UTXOs reserved for an order back to the wallet, if any.
+    pub(super) async fn release_reservation(
+        &self,
+        order_id: &str,
+        reason: &str,
+    ) -> Option<Reservation> {
+        let reservation = self.store.borrow_mut().reservations.remove(order_id)?;
+
+        info!(
+            "Releasing funds reserved for order {} ({})",
+            order_id, reason
+        );
+        self.release_outputs(&reservation.outpoints).await;
+
+        if let Err(e) = self.store.borrow().save() {
+            warn!("Error saving state: {}", e);
+        }
+
+        Some(reservation)
+    }
+
+    /// Leases released UTXOs again for the rest of their reservation, e.g.
+    /// when the channel they were released for couldn't be opened.
+    pub(super) async fn restore_reservation(&self, order_id: &str, reservation: Reservation) {
+        let seconds = reservation.expires_at.saturating_sub(now());
+        if seconds == 0 {
+            return;
+        }
+
+        for (leased, outpoint) in reservation.outpoints.iter().enumerate() {
+            if let Err(e) = self.node.lease_output(outpoint, seconds).await {
+                warn!(
+                    "Error reserving {} again for order {}, dropping its reservation: {}",
+                    outpoint, order_id, e
+                );
+                self.release_outputs(&reservation.outpoints[..leased]).await;
+                return;
+            }
+        }
+
+        info!("Reserved funds for order {} again", order_id);
+        let mut store = self.store.borrow_mut();
+        store.reservations.insert(order_id.to_string(), reservation);
+        if let Err(e) = store.save() {
+            warn!("Error saving state: {}", e);
+        }
+    }
+
+    /// Forgets reservations whose leases LND already let go.
+    pub(super) fn expire_reservations(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut store = self.store.borrow_mut();
+        let now = now();
+
+        let before = store.reservations.len();
+        store.reservations.retain(|order_id, reservation| {
+            let expired = reservation.expires_at <= now;
+            if expired {
+                info!("Funds reserved for order {} expired", order_id);
+            }
+            !expired
+        });
+
+        if store.reservations.len() != before {
+            store.save()?;
+        }
+
+        Ok(())
+    }
+
+    async fn release_outputs(&self, outpoints: &[String]) {
+        for outpoint in outpoints {
+            if let Err(e) = self.node.release_output(outpoint).await {
+                // The lease may already be gone, e.g. expired or spent
+                debug!("Error releasing {}: {}", outpoint, e);
+            }
+        }
+    }
+}
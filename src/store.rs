@@ -0,0 +1,174 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+
+use crate::approval::Decision;
+
+const STATE_FILE: &str = ".amboss_magma_bot.state.json";
+
+/// Where a sold channel is in its life, as seen by the bot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChannelStage {
+    /// Funding transaction broadcast, waiting for confirmations
+    Pending,
+    /// Channel confirmed and usable
+    Open,
+    /// Channel closed, by either side
+    Closed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SoldChannel {
+    pub order_id: String,
+    pub buyer: String,
+    pub size: i64,
+    pub channel_point: String,
+    pub chan_id: Option<u64>,
+    pub stage: ChannelStage,
+    /// Last status reported by Magma for the order
+    pub magma_status: Option<String>,
+    /// Minimum number of blocks the channel has to stay open
+    pub min_block_length: u32,
+    /// Height of the block that confirmed the funding transaction
+    pub confirmation_height: Option<u32>,
+    /// Height at which we noticed the minimum lease was over
+    pub lease_elapsed_height: Option<u32>,
+    /// First height at which the channel can be closed without breaking the lease
+    #[serde(default)]
+    pub lease_end_height: Option<u32>,
+    /// Highest outbound fee rate (ppm) allowed while under lease
+    #[serde(default)]
+    pub fee_rate_cap: Option<u32>,
+    /// Highest outbound base fee allowed while under lease, as locked by the
+    /// order, in the configured `base_fee_cap_unit`
+    #[serde(default)]
+    pub base_fee_cap: Option<i64>,
+    /// Height at which the bot first saw the funding transaction unconfirmed
+    #[serde(default)]
+    pub broadcast_height: Option<u32>,
+    /// Fee rate (sat/vB) of the last bump of the funding transaction
+    #[serde(default)]
+    pub bumped_fee_rate: Option<u64>,
+}
+
+/// Changes the bot made to an offer because of low liquidity, to undo later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OfferAdjustment {
+    /// `max_size` before the bot shrunk it
+    pub original_max_size: Option<f64>,
+    /// Whether the bot disabled the offer
+    pub disabled: bool,
+}
+
+/// UTXOs leased in LND for an accepted order until its channel is opened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reservation {
+    /// Outpoints as `txid:output_index`
+    pub outpoints: Vec<String>,
+    pub amount: i64,
+    /// Unix time after which LND releases the leases by itself
+    pub expires_at: u64,
+}
+
+/// A Magma status an order went through.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: String,
+    /// Unix time at which the bot noticed it
+    pub at: u64,
+}
+
+/// An order the bot parked for the operator to approve.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApprovalRecord {
+    pub reason: String,
+    /// Unix time at which the order was parked
+    pub requested_at: u64,
+    /// Operator's decision, or the bot's one when the operator didn't answer in time
+    pub decision: Option<Decision>,
+    /// Unix time of the decision
+    pub decided_at: Option<u64>,
+}
+
+/// What the bot did for an order, besides its channel.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrderRecord {
+    /// Hex payment hash of the invoice sent when accepting the order
+    pub payment_hash: Option<String>,
+    /// Unix time at which that invoice expires
+    pub invoice_expires_at: Option<u64>,
+    /// Invoices sent again after the previous one expired
+    #[serde(default)]
+    pub invoice_renewals: u32,
+    /// Last status reported by Magma
+    #[serde(default)]
+    pub status: Option<String>,
+    /// Statuses seen so far, oldest first
+    #[serde(default)]
+    pub history: Vec<StatusChange>,
+    /// Sats earned once Magma validated the channel
+    #[serde(default)]
+    pub revenue: Option<i64>,
+    /// Chat messages already forwarded, or seen before the bot read the chat
+    #[serde(default)]
+    pub messages_seen: Option<usize>,
+    /// Set when the order waited for the operator's approval
+    #[serde(default)]
+    pub approval: Option<ApprovalRecord>,
+}
+
+/// Bot state persisted between restarts.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Store {
+    /// Channels sold through Magma, keyed by order id
+    pub channels: HashMap<String, SoldChannel>,
+    /// Offers paused or shrunk for lack of funds, keyed by offer id
+    #[serde(default)]
+    pub offer_adjustments: HashMap<String, OfferAdjustment>,
+    /// Funds reserved for accepted orders, keyed by order id
+    #[serde(default)]
+    pub reservations: HashMap<String, Reservation>,
+    /// Orders seen on Magma, keyed by order id
+    #[serde(default)]
+    pub orders: HashMap<String, OrderRecord>,
+    /// Settle index of the last invoice handled, to resume the subscription from
+    #[serde(default)]
+    pub last_settle_index: u64,
+    /// Whether the operator paused order processing
+    #[serde(default)]
+    pub paused: bool,
+}
+
+impl Store {
+    pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = match fs::read_to_string(STATE_FILE) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("No state file found, starting fresh");
+                return Ok(Self::default());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Writes a temporary file next to the state file and renames it over, so a
+    /// crash or a full disk never leaves a truncated state behind.
+    pub fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let tmp = format!("{}.tmp", STATE_FILE);
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&tmp, STATE_FILE)?;
+        Ok(())
+    }
+
+    pub fn channel_by_point(&mut self, channel_point: &str) -> Option<&mut SoldChannel> {
+        self.channels
+            .values_mut()
+            .find(|channel| channel.channel_point == channel_point)
+    }
+}